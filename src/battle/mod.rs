@@ -1,9 +1,11 @@
+pub mod stage;
+
 use bevy::{
     core::Name,
     gltf::{Gltf},
     prelude::{
         default, shape, AssetServer, Assets, BuildChildren, Camera3dBundle, Color, Commands, Component, Entity,
-        Handle, KeyCode, Mesh, NodeBundle, Parent, PbrBundle, PointLight,
+        Handle, Mesh, NodeBundle, Parent, PbrBundle, PointLight,
         PointLightBundle, Query, Res, ResMut, Resource, StandardMaterial,
         TextBundle, Transform, Vec3, Visibility, With, SpatialBundle, Quat,
     },
@@ -22,7 +24,7 @@ use ggrs::{SessionBuilder};
 
 
 use leafwing_input_manager::{
-    prelude::{ActionState, InputMap},
+    prelude::ActionState,
     InputManagerBundle,
 };
 use parry3d::shape::{Capsule, Cuboid};
@@ -32,7 +34,8 @@ use crate::{
         data::{Collider, FighterData},
         modifiers::{CreateObject, InputMet, InputWindowCheck, Object, OnExitSetPos},
         state::{
-            ActiveHitboxes, BoneMap, CurrentState, Direction, Facing, Health, HurtboxData,
+            AbsorbedHits, ActiveHitboxes, ActiveHurtboxes, BoneMap, Contact, CurrentState, Direction, Exclude, Facing, Health, Hitstop, Juggle, Combo, LastCounter, Meter,
+            HurtboxData,
             Hurtboxes, Owner, PlayerAxis, ProjectileReference, SerializedStateVec,
             State as FightState, StateFrame, Velocity,
         },
@@ -40,11 +43,16 @@ use crate::{
         Fighter, animation::components::FullBoneTransformMap,
     },
     game::{GameState, RoundState},
-    input::{Action, BUFFER_SIZE},
+    input::{
+        bindings::{InputConfig, InputConfigHandles, InputSlot},
+        Action, LocalPlayers, BUFFER_SIZE,
+    },
     util::Buffer,
     GGRSConfig, GameDebug, Player,
 };
 
+use self::stage::{Stage, StageHandle};
+
 #[derive(Resource)]
 pub struct PlayerEntities(pub Entity, pub Entity);
 
@@ -64,6 +72,11 @@ impl From<&PlayerEntities> for [Entity; 2] {
     }
 }
 
+/// GGRS session built at launch. It is only handed to GGRS once the round is set up,
+/// so that rollbacks can't happen while fighters are still being spawned.
+#[derive(Resource)]
+pub struct PendingSession(pub Option<Session<GGRSConfig>>);
+
 #[derive(Debug)]
 pub struct PlayerHandles {
     pub state_list: Handle<SerializedStateVec>,
@@ -141,8 +154,10 @@ pub fn loading_wait(
     mut state: ResMut<RoundState>,
 
     player_access: Res<PlayerHandleAccess>,
+    input_configs: Res<InputConfigHandles>,
+    stage: Res<StageHandle>,
 ) {
-    let handles = vec![
+    let mut handles = vec![
         player_access.0.fighter_data.id(),
         player_access.0.state_list.id(),
         player_access.1.fighter_data.id(),
@@ -152,6 +167,8 @@ pub fn loading_wait(
         player_access.0.bones.id(),
         player_access.1.bones.id()
     ];
+    handles.extend(input_configs.0.iter().map(|handle| handle.id()));
+    handles.push(stage.0.id());
 
     println!("LOADING...");
 
@@ -180,6 +197,11 @@ pub fn spawn_fighters(
 
     mut state: ResMut<RoundState>,
     debug: Res<GameDebug>,
+    local_players: Option<Res<LocalPlayers>>,
+    input_configs: Res<Assets<InputConfig>>,
+    input_config_handles: Res<InputConfigHandles>,
+    stages: Res<Assets<Stage>>,
+    stage_handle: Res<StageHandle>,
 ) {
     let fighter1 = data
         .remove(&handle_access.0.fighter_data)
@@ -228,28 +250,21 @@ pub fn spawn_fighters(
         .insert(BoneMap(HashMap::new()))
         .insert(ActiveHitboxes(Vec::new()))
         .insert(Health(500))
+        .insert(Contact::default())
+        .insert(AbsorbedHits::default())
+        .insert(Hitstop::default())
+        .insert(Juggle::default())
+        .insert(Combo::default())
+        .insert(LastCounter::default())
+        .insert(Meter::default())
         .insert(Velocity(Vec3::ZERO))
         .insert(Hurtboxes::new())
+        .insert(ActiveHurtboxes::default())
         .insert(PlayerAxis {
             opponent_pos: Vec3::new(2., 0., 0.),
             x: Vec3::X,
             z: Vec3::Z,
         })
-        .insert(InputManagerBundle::<Action> {
-            action_state: ActionState::default(),
-            input_map: InputMap::new([
-                (KeyCode::U, Action::A),
-                (KeyCode::I, Action::B),
-                (KeyCode::O, Action::C),
-                (KeyCode::J, Action::J),
-                (KeyCode::K, Action::K),
-                (KeyCode::L, Action::L),
-                (KeyCode::A, Action::Left),
-                (KeyCode::D, Action::Right),
-                (KeyCode::W, Action::Up),
-                (KeyCode::S, Action::Down),
-            ]),
-        })
         .id();
 
     let player2 = commands
@@ -276,8 +291,16 @@ pub fn spawn_fighters(
         .insert(InputBuffer(Buffer::with_capacity(BUFFER_SIZE)))
         .insert(BoneMap(HashMap::new()))
         .insert(Health(500))
+        .insert(Contact::default())
+        .insert(AbsorbedHits::default())
+        .insert(Hitstop::default())
+        .insert(Juggle::default())
+        .insert(Combo::default())
+        .insert(LastCounter::default())
+        .insert(Meter::default())
         .insert(Velocity(Vec3::ZERO))
         .insert(Hurtboxes::new())
+        .insert(ActiveHurtboxes::default())
         .insert(PlayerAxis {
             opponent_pos: Vec3::new(-2., 0., 0.),
             x: Vec3::X,
@@ -285,6 +308,24 @@ pub fn spawn_fighters(
         })
         .id();
 
+    // Every player local to this machine gets their own controls, in the order of their handles
+    let local_handles = local_players.map_or_else(|| vec![0, 1], |local| local.0.clone());
+
+    for (slot, handle) in local_handles.into_iter().enumerate() {
+        let input_config = input_configs
+            .get(&input_config_handles.0[slot])
+            .expect("InputConfig asset does not exist");
+
+        commands
+            .entity(if handle == 0 { player1 } else { player2 })
+            .insert(InputManagerBundle::<Action> {
+                action_state: ActionState::default(),
+                input_map: input_config.input_map(),
+            })
+            .insert(input_config.socd)
+            .insert(InputSlot(slot));
+    }
+
     if !debug.0 {
         commands
             .entity(player1)
@@ -338,15 +379,25 @@ pub fn spawn_fighters(
 
         });
 
+    let stage = stages
+        .get(&stage_handle.0)
+        .expect("Stage asset does not exist")
+        .clone();
+
     commands
         .spawn(PbrBundle {
             //mesh: meshes.add(Mesh::from(shape::Plane { size: 100. })),
-            mesh: meshes.add(Mesh::from(shape::Plane { size: 100., subdivisions: 0 })),
+            mesh: meshes.add(Mesh::from(shape::Plane {
+                size: stage.bounds.floor_size(),
+                subdivisions: 0,
+            })),
             material: materials.add(Color::WHITE.into()),
             ..default()
         })
         .insert(Name::new("Ground"));
 
+    commands.insert_resource(stage);
+
     *state = RoundState::Armature
 }
 
@@ -379,6 +430,8 @@ pub fn extra_setup_system(
     bone_parent_query: Query<&Parent>,
 
     player_query: Query<Entity, (With<Player>, With<Fighter>)>,
+
+    pending_session: Option<ResMut<PendingSession>>,
 ) {
     let projectile_material = materials.add(Color::rgba(0., 1., 0., 0.5).into());
     println!("Start of projectile system");
@@ -429,6 +482,7 @@ pub fn extra_setup_system(
                         .insert(Rollback::new(rip.next_id()))
                         .insert(StateFrame(0))
                         .insert(Owner(owner.get()))
+                        .insert(Exclude::default())
                         .id();
 
                     ids.push((entity, false))
@@ -488,41 +542,27 @@ pub fn extra_setup_system(
     }
 
     for (mut fight_state, parent) in state_query.iter_mut() {
+        let Ok(mut bonemap) = bonemap_query.get_mut(parent.get()) else {
+            continue;
+        };
+
         if let Some(hitboxes) = &mut fight_state.hitboxes {
-            for boxes in hitboxes.values_mut() {
-                for hitbox in boxes {
-                    let bone_name = hitbox.bone.clone();
-
-                    if let Ok(mut bonemap) = bonemap_query.get_mut(parent.get()) {
-                        if let Some(bone_entity) = bonemap.0.get(&bone_name) {
-                            hitbox.bone_entity = Some(*bone_entity);
-                        } else {
-                            for (name, bone_entity) in bone_name_query.iter() {
-                                if name.to_string() == bone_name {
-                                    let mut ancestor = bone_parent_query
-                                        .get(bone_entity)
-                                        .expect("Bone doesn't have parent");
-                                    loop {
-                                        if let Ok(bone_parent) =
-                                            bone_parent_query.get(ancestor.get())
-                                        {
-                                            ancestor = bone_parent;
-                                        } else {
-                                            break;
-                                        }
-                                    }
-
-                                    if parent.get() == ancestor.get() {
-                                        bonemap.0.insert(bone_name, bone_entity);
-                                        hitbox.bone_entity = Some(bone_entity);
-                                        println!("It somehow got here, doing bone things");
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+            for hitbox in hitboxes.values_mut().flatten() {
+                hitbox.bone_entity = find_bone(
+                    &hitbox.bone,
+                    parent.get(),
+                    &mut bonemap,
+                    &bone_name_query,
+                    &bone_parent_query,
+                );
+            }
+        }
+
+        if let Some(hurtboxes) = &mut fight_state.hurtboxes {
+            for hurtbox in hurtboxes.values_mut().flatten() {
+                hurtbox.bone_entity = hurtbox.bone.as_ref().and_then(|bone| {
+                    find_bone(bone, parent.get(), &mut bonemap, &bone_name_query, &bone_parent_query)
+                });
             }
         }
     }
@@ -537,8 +577,48 @@ pub fn extra_setup_system(
     //         .insert(Rollback::new(rip.next_id()));
     // }
 
+    let sess = pending_session
+        .and_then(|mut pending| pending.0.take())
+        .unwrap_or_else(local_session);
+    commands.insert_resource(sess);
+
+    *round_state = RoundState::Round;
+}
+
+/// The fighter's bone with the given name, which is cached in its `BoneMap`
+fn find_bone(
+    bone_name: &str,
+    fighter: Entity,
+    bonemap: &mut BoneMap,
+    bone_name_query: &Query<(&Name, Entity), With<Transform>>,
+    bone_parent_query: &Query<&Parent>,
+) -> Option<Entity> {
+    if let Some(bone_entity) = bonemap.0.get(bone_name) {
+        return Some(*bone_entity);
+    }
+
+    for (name, bone_entity) in bone_name_query.iter() {
+        if name.as_str() == bone_name {
+            let mut ancestor = bone_parent_query
+                .get(bone_entity)
+                .expect("Bone doesn't have parent");
+            while let Ok(bone_parent) = bone_parent_query.get(ancestor.get()) {
+                ancestor = bone_parent;
+            }
+
+            if fighter == ancestor.get() {
+                bonemap.0.insert(bone_name.to_string(), bone_entity);
+                return Some(bone_entity);
+            }
+        }
+    }
+
+    None
+}
+
+/// Both players on this machine, with no rollback checks. Used when no session was set up at launch.
+fn local_session() -> Session<GGRSConfig> {
     let sess_build = SessionBuilder::<GGRSConfig>::new()
-        //.with_max_prediction_window(8)
         .with_check_distance(0)
         .with_input_delay(2)
         .with_num_players(2)
@@ -550,9 +630,7 @@ pub fn extra_setup_system(
     let sess = sess_build
         .start_synctest_session()
         .expect("Couldn't start Session");
-    commands.insert_resource(Session::SyncTestSession(sess));
-
-    *round_state = RoundState::Round;
+    Session::SyncTestSession(sess)
 }
 
 #[derive(Component)]
@@ -574,6 +652,17 @@ impl Lifebar {
     }
 }
 
+#[derive(Component)]
+pub struct MeterBar;
+
+/// Hits and damage of the combo a player is dealing
+#[derive(Component)]
+pub struct ComboCounter;
+
+/// Shown when a player lands a counter hit
+#[derive(Component)]
+pub struct CounterPopup;
+
 #[derive(Component)]
 pub struct ChangeText;
 
@@ -583,6 +672,16 @@ pub fn create_battle_ui(
     asset_server: Res<AssetServer>,
 ) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let combo_style = TextStyle {
+        font: font.clone(),
+        font_size: 26.0,
+        color: Color::WHITE,
+    };
+    let counter_style = TextStyle {
+        font: font.clone(),
+        font_size: 30.0,
+        color: Color::ORANGE_RED,
+    };
 
     commands
         .spawn(NodeBundle {
@@ -642,6 +741,71 @@ pub fn create_battle_ui(
                                 .insert(Lifebar::new(500))
                                 .insert(Name::new("Player 1 Lifebar Fill"));
                         });
+
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::RowReverse,
+                                size: Size::new(Val::Percent(60.), Val::Percent(8.)),
+                                position: UiRect {
+                                    top: Val::Percent(33.),
+                                    ..default()
+                                },
+                                align_self: AlignSelf::FlexEnd,
+                                ..default()
+                            },
+                            background_color: Color::BLACK.into(),
+                            ..default()
+                        })
+                        .insert(Name::new("Player 1 Meter"))
+                        .with_children(|parent| {
+                            parent
+                                .spawn(NodeBundle {
+                                    style: Style {
+                                        size: Size::new(Val::Percent(0.), Val::Percent(100.)),
+                                        ..default()
+                                    },
+                                    background_color: Color::BLUE.into(),
+                                    ..default()
+                                })
+                                .insert(Player(1))
+                                .insert(MeterBar)
+                                .insert(Name::new("Player 1 Meter Fill"));
+                        });
+
+                    parent
+                        .spawn(
+                            TextBundle::from_sections([
+                                TextSection::from_style(combo_style.clone()),
+                                TextSection::from_style(combo_style.clone()),
+                            ])
+                            .with_style(Style {
+                                position: UiRect {
+                                    top: Val::Percent(40.),
+                                    ..default()
+                                },
+                                align_self: AlignSelf::FlexStart,
+                                ..default()
+                            }),
+                        )
+                        .insert(Player(1))
+                        .insert(ComboCounter)
+                        .insert(Name::new("Player 1 Combo Counter"));
+
+                    parent
+                        .spawn(
+                            TextBundle::from_section("", counter_style.clone()).with_style(Style {
+                                position: UiRect {
+                                    top: Val::Percent(45.),
+                                    ..default()
+                                },
+                                align_self: AlignSelf::FlexStart,
+                                ..default()
+                            }),
+                        )
+                        .insert(Player(1))
+                        .insert(CounterPopup)
+                        .insert(Name::new("Player 1 Counter Popup"));
                 });
 
             // Player 2
@@ -690,6 +854,71 @@ pub fn create_battle_ui(
                                 .insert(Lifebar::new(500))
                                 .insert(Name::new("Player 2 Lifebar Fill"));
                         });
+
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                size: Size::new(Val::Percent(60.), Val::Percent(8.)),
+                                position: UiRect {
+                                    top: Val::Percent(33.),
+                                    ..default()
+                                },
+                                align_self: AlignSelf::FlexStart,
+                                ..default()
+                            },
+                            background_color: Color::BLACK.into(),
+                            ..default()
+                        })
+                        .insert(Name::new("Player 2 Meter"))
+                        .with_children(|parent| {
+                            parent
+                                .spawn(NodeBundle {
+                                    style: Style {
+                                        size: Size::new(Val::Percent(0.), Val::Percent(100.)),
+                                        ..default()
+                                    },
+                                    background_color: Color::BLUE.into(),
+                                    ..default()
+                                })
+                                .insert(Player(2))
+                                .insert(MeterBar)
+                                .insert(Name::new("Player 2 Meter Fill"));
+                        });
+
+                    parent
+                        .spawn(
+                            TextBundle::from_sections([
+                                TextSection::from_style(combo_style.clone()),
+                                TextSection::from_style(combo_style.clone()),
+                            ])
+                            .with_style(Style {
+                                position: UiRect {
+                                    top: Val::Percent(40.),
+                                    ..default()
+                                },
+                                align_self: AlignSelf::FlexEnd,
+                                ..default()
+                            }),
+                        )
+                        .insert(Player(2))
+                        .insert(ComboCounter)
+                        .insert(Name::new("Player 2 Combo Counter"));
+
+                    parent
+                        .spawn(
+                            TextBundle::from_section("", counter_style.clone()).with_style(Style {
+                                position: UiRect {
+                                    top: Val::Percent(45.),
+                                    ..default()
+                                },
+                                align_self: AlignSelf::FlexEnd,
+                                ..default()
+                            }),
+                        )
+                        .insert(Player(2))
+                        .insert(CounterPopup)
+                        .insert(Name::new("Player 2 Counter Popup"));
                 });
         });
 
@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::{Deserialize, Serialize};
+
+use crate::fighter::{
+    hit::components::AirborneHitstun,
+    state::{Blockstun, GroundedHitstun, Velocity},
+    Fighter,
+};
+
+use super::PlayerEntities;
+
+pub const STAGE_PATH: &str = "data/stages/training.stage";
+// How close to a wall counts as touching it
+const WALL_MARGIN: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StageBounds {
+    // Walls on both sides of each axis
+    #[serde(rename_all = "camelCase")]
+    Arena { half_width: f32, half_depth: f32 },
+    // A round wall around the center of the stage
+    Ring { radius: f32 },
+}
+
+impl StageBounds {
+    /// The closest position to this one that's inside the walls
+    pub fn clamp(&self, position: Vec3) -> Vec3 {
+        match *self {
+            StageBounds::Arena {
+                half_width,
+                half_depth,
+            } => Vec3::new(
+                position.x.clamp(-half_width, half_width),
+                position.y,
+                position.z.clamp(-half_depth, half_depth),
+            ),
+            StageBounds::Ring { radius } => {
+                let flat = position.xz().clamp_length_max(radius);
+                Vec3::new(flat.x, position.y, flat.y)
+            }
+        }
+    }
+
+    /// Whether the position is up against a wall
+    pub fn at_wall(&self, position: Vec3) -> bool {
+        match *self {
+            StageBounds::Arena {
+                half_width,
+                half_depth,
+            } => {
+                position.x.abs() >= half_width - WALL_MARGIN
+                    || position.z.abs() >= half_depth - WALL_MARGIN
+            }
+            StageBounds::Ring { radius } => position.xz().length() >= radius - WALL_MARGIN,
+        }
+    }
+
+    pub fn floor_size(&self) -> f32 {
+        match *self {
+            StageBounds::Arena {
+                half_width,
+                half_depth,
+            } => 2. * half_width.max(half_depth),
+            StageBounds::Ring { radius } => 2. * radius,
+        }
+    }
+}
+
+/// Walls of the stage and how far apart the fighters can get, loaded from a `.stage` file
+#[derive(Debug, Clone, Serialize, Deserialize, TypeUuid, Resource)]
+#[uuid = "0f6b2d7c-3a5e-4e1b-8c9d-2b7a4f1e6d53"]
+#[serde(rename_all = "camelCase")]
+pub struct Stage {
+    pub bounds: StageBounds,
+    // Keeps both fighters in view of the camera
+    pub max_distance: f32,
+}
+
+#[derive(Resource)]
+pub struct StageHandle(pub Handle<Stage>);
+
+pub fn load_stage(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(StageHandle(asset_server.load(STAGE_PATH)));
+}
+
+/// Keeps the fighters within `max_distance` of each other and inside the walls. A fighter
+/// knocked back into a wall can't go any further, so the attacker is pushed back instead.
+pub fn stage_system(
+    players: Res<PlayerEntities>,
+    stage: Res<Stage>,
+    mut fighter_query: Query<
+        (
+            &mut Transform,
+            Option<&GroundedHitstun>,
+            Option<&AirborneHitstun>,
+            Option<&Blockstun>,
+        ),
+        With<Fighter>,
+    >,
+) {
+    let Ok(
+        [(mut tf1, hitstun1, airborne1, blockstun1), (mut tf2, hitstun2, airborne2, blockstun2)],
+    ) = fighter_query.get_many_mut(players.as_ref().into())
+    else {
+        return;
+    };
+
+    let mut between = tf2.translation - tf1.translation;
+    between.y = 0.;
+
+    let excess = between.length() - stage.max_distance;
+    if excess > 0. {
+        let pull = between.normalize() * excess / 2.;
+        tf1.translation += pull;
+        tf2.translation -= pull;
+    }
+
+    let correction1 = stage.bounds.clamp(tf1.translation) - tf1.translation;
+    let correction2 = stage.bounds.clamp(tf2.translation) - tf2.translation;
+
+    tf1.translation += correction1;
+    tf2.translation += correction2;
+
+    if hitstun1.is_some() || airborne1.is_some() || blockstun1.is_some() {
+        tf2.translation += correction1;
+    }
+    if hitstun2.is_some() || airborne2.is_some() || blockstun2.is_some() {
+        tf1.translation += correction2;
+    }
+
+    // The pushed back attacker could have reached a wall of its own
+    tf1.translation = stage.bounds.clamp(tf1.translation);
+    tf2.translation = stage.bounds.clamp(tf2.translation);
+}
+
+/// Bounces fighters knocked into a wall by a hit with a wall bounce back towards their opponent
+pub fn wall_bounce_system(
+    stage: Res<Stage>,
+    mut fighter_query: Query<(&Transform, &mut Velocity, &mut AirborneHitstun), With<Fighter>>,
+) {
+    for (tf, mut velo, mut airborne) in fighter_query.iter_mut() {
+        // Velocity is along the axis to the opponent, so it's away from them while negative
+        if velo.0.x >= 0. || !stage.bounds.at_wall(tf.translation) {
+            continue;
+        }
+
+        if let Some(bounce) = airborne.wall_bounce.take() {
+            velo.0 = bounce;
+        }
+    }
+}
+
+#[test]
+fn walls_clamp_positions() {
+    let arena = StageBounds::Arena {
+        half_width: 5.,
+        half_depth: 3.,
+    };
+    assert_eq!(arena.clamp(Vec3::new(7., 1., -4.)), Vec3::new(5., 1., -3.));
+    assert_eq!(arena.clamp(Vec3::new(1., 0., 1.)), Vec3::new(1., 0., 1.));
+
+    let ring = StageBounds::Ring { radius: 5. };
+    assert_eq!(ring.clamp(Vec3::new(0., 2., 10.)), Vec3::new(0., 2., 5.));
+    assert_eq!(ring.clamp(Vec3::new(3., 0., 0.)), Vec3::new(3., 0., 0.));
+}
+
+#[test]
+fn positions_at_the_walls() {
+    let arena = StageBounds::Arena {
+        half_width: 5.,
+        half_depth: 3.,
+    };
+    assert!(arena.at_wall(Vec3::new(-5., 1., 0.)));
+    assert!(!arena.at_wall(Vec3::new(4., 0., 2.)));
+
+    let ring = StageBounds::Ring { radius: 5. };
+    assert!(ring.at_wall(ring.clamp(Vec3::new(6., 0., 6.))));
+    assert!(!ring.at_wall(Vec3::new(3., 0., 0.)));
+}
@@ -1,4 +1,4 @@
-use bevy_fighting_lib::battle::{create_battle_ui, extra_setup_system, load_fighters, loading_wait, spawn_fighters};
+use bevy_fighting_lib::battle::{create_battle_ui, extra_setup_system, load_fighters, loading_wait, spawn_fighters, stage::{stage_system, wall_bounce_system}, PendingSession};
 use bevy_fighting_lib::util::scripting::{LuaAPIProvider, PlayerEntityArg };
 use bevy_fighting_lib::{GGRSConfig, FPS, GameDebug, Player, util};
 use bevy_fighting_lib::fighter;
@@ -12,34 +12,36 @@ use bevy_editor_pls::EditorPlugin;
 use bevy_ggrs::{GGRSPlugin, GGRSSchedule, Rollback, RollbackIdProvider, Session};
 
 use bevy_fighting_lib::fighter::{
-    hit::components::{AirborneHitstun, HitboxData},
+    hit::components::{AirborneHitstun, HitboxData, KnockedDown},
     state::{
-        Active, ActiveHitboxes, CurrentState, Facing, GroundedHitstun, HBox, Health, HurtboxData,
+        AbsorbedHits, Active, ActiveHitboxes, ActiveHurtboxes, Blockstun, Contact, CurrentState, Facing, Grabbed, GroundedHitstun, HBox, Health, Hitstop, HurtboxData, Juggle, Combo, LastCounter, Meter,
         PlayerAxis, ProjectileReference, SerializedStateVec, StateFrame, Velocity,
     },
     systems::{
         axis_system, buffer_insert_system, camera_system, collision_system, hbox_position_system,
-        hit_event_system, hitbox_component_system, hitbox_removal_system, hitstun_system,
-        hurtbox_component_system, hurtbox_removal_system, increment_frame_system,
+        hit_event_system, hitbox_component_system, hitbox_removal_system, hitstop_system, hitstun_system, last_counter_system,
+        hurtbox_component_system, hurtbox_removal_system, increment_frame_system, meter_system,
         modifier_input_check, movement_system, object_system, pause_system, process_input_system,
-        projectile_system, transition_system, ui_lifebar_system, InputBuffer, NonRollbackSet,
+        projectile_system, pushbox_system, throw_system, transition_system, ui_combo_system, ui_counter_system, ui_lifebar_system, ui_meter_system, InputBuffer, NonRollbackSet,
         RollbackSet, SetupSet,
     },
     Fighter, FighterPlugin,
 };
 use bevy_fighting_lib::game::{
-    debug::state_text_system, not_if_paused, on_armature, on_enter_loading, on_enter_round,
+    debug::state_text_system,
+    not_if_paused, on_armature, on_enter_loading, on_enter_round,
     on_exit_loading, on_extra_setup, on_loading, on_round, paused_advance_or_round, Paused,
     RoundState, ADD_HITBOX, ADD_HURTBOX, AXIS, COLLISION, FRAME_INCREMENT, HITSTUN, HIT_EVENT,
     INPUT_BUFFER, MOD_INPUT_CHECK, MOVEMENT, PROCESS, PROJECTILE, REMOVE_HITBOX, REMOVE_HURTBOX,
     TRANSITION, UPDATE_HIT_POS, UPDATE_HURT_POS, SetupPlugin,
+    synctest::{unordered_checksum, ChecksumComponents, RollbackFrame, SyncTestPlugin},
 };
 use bevy_mod_scripting::prelude::{ScriptingPlugin, AddScriptHostHandler, AddScriptHost, LuaScriptHost, script_event_handler, AddScriptApiProvider, GenDocumentation, LuaBevyAPIProvider};
-use ggrs::Config;
+use ggrs::{Config, PlayerHandle, PlayerType, SessionBuilder, UdpNonBlockingSocket};
 //use bevy_editor_pls::prelude::*;
 
 use bevy_common_assets::json::JsonAssetPlugin;
-use bevy_fighting_lib::input::Action;
+use bevy_fighting_lib::input::{bindings::rebind_system, Action, LocalPlayers};
 
 use leafwing_input_manager::prelude::InputManagerPlugin;
 
@@ -66,30 +68,111 @@ use bevy_fighting_lib::{
 
 
 
-// #[derive(StructOpt)]
-// struct Opt {
-//     #[structopt(short, long)]
-//     local_port: u16,
-//     #[structopt(short, long)]
-//     players: Vec<String>,
-//     #[structopt(short, long)]
-//     spectators: Vec<SocketAddr>
-
-// }
-
 // #[derive(Resource)]
 // pub struct GameDebug(pub bool);
 
+const NUM_PLAYERS: usize = 2;
+
 #[derive(StructOpt)]
 struct Opt {
+    #[structopt(short, long, default_value = "7000")]
+    local_port: u16,
+    // "localhost" for a player on this machine, otherwise the remote player's ip:port
     #[structopt(short, long)]
-    num_players: usize,
+    players: Vec<String>,
     #[structopt(short, long)]
-    check_distance: usize,
+    spectators: Vec<SocketAddr>,
+    #[structopt(short, long, default_value = "2")]
+    input_delay: usize,
+    #[structopt(short, long, default_value = "8")]
+    max_prediction: usize,
+    // Runs a SyncTestSession that resimulates this many frames every frame
+    #[structopt(short, long = "synctest")]
+    check_distance: Option<usize>,
     #[structopt(short, long)]
     debug_mode: bool,
 }
 
+/// The session asked for on the command line
+#[derive(Debug)]
+enum SessionKind {
+    // Both players are local and nothing goes over the network
+    Offline,
+    SyncTest(usize),
+    // Every player and spectator, indexed by their handle
+    P2P(Vec<PlayerType<SocketAddr>>),
+}
+
+/// A SyncTest session with `--synctest`, otherwise a P2P session from the players given.
+/// With no players given, the match is offline.
+fn session_kind(opt: &Opt) -> Result<SessionKind, Box<dyn std::error::Error>> {
+    if let Some(check_distance) = opt.check_distance {
+        return Ok(SessionKind::SyncTest(check_distance));
+    }
+
+    if opt.players.is_empty() {
+        return Ok(SessionKind::Offline);
+    }
+
+    if opt.players.len() != NUM_PLAYERS {
+        return Err(format!("Expected {} players, got {}", NUM_PLAYERS, opt.players.len()).into());
+    }
+
+    let mut players = Vec::new();
+
+    for player_addr in &opt.players {
+        if player_addr == "localhost" {
+            players.push(PlayerType::Local);
+        } else {
+            players.push(PlayerType::Remote(player_addr.parse()?));
+        }
+    }
+
+    players.extend(opt.spectators.iter().map(|addr| PlayerType::Spectator(*addr)));
+
+    Ok(SessionKind::P2P(players))
+}
+
+/// Starts the session from the command line, along with the handles of the players local to this machine
+fn start_session(opt: &Opt) -> Result<(Option<Session<GGRSConfig>>, Vec<PlayerHandle>), Box<dyn std::error::Error>> {
+    match session_kind(opt)? {
+        SessionKind::Offline => Ok((None, vec![0, 1])),
+        SessionKind::SyncTest(check_distance) => {
+            let sess = SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(NUM_PLAYERS)
+                .with_check_distance(check_distance)
+                .with_input_delay(opt.input_delay)
+                .add_player(PlayerType::Local, 0)?
+                .add_player(PlayerType::Local, 1)?
+                .start_synctest_session()?;
+
+            Ok((Some(Session::SyncTestSession(sess)), vec![0, 1]))
+        }
+        SessionKind::P2P(players) => {
+            let mut sess_build = SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(NUM_PLAYERS)
+                .with_input_delay(opt.input_delay)
+                .with_max_prediction_window(opt.max_prediction);
+
+            let local_players: Vec<PlayerHandle> = players
+                .iter()
+                .enumerate()
+                .filter(|(_, player)| matches!(player, PlayerType::Local))
+                .map(|(handle, _)| handle)
+                .collect();
+
+            for (handle, player) in players.into_iter().enumerate() {
+                sess_build = sess_build.add_player(player, handle)?;
+            }
+
+            let socket = UdpNonBlockingSocket::bind_to_port(opt.local_port)?;
+            let sess = sess_build.start_p2p_session(socket)?;
+
+            Ok((Some(Session::P2PSession(sess)), local_players))
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env::set_var("RUST_BACKTRACE", "1");
@@ -119,37 +202,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // }
 
     let opt = Opt::from_args();
-    let num_players: usize = 2;
+    let (session, local_players) = start_session(&opt)?;
 
     let mut app = App::new();
 
-    // let mut sess_build = SessionBuilder::<GGRSConfig>::new()
-    //     //.with_max_prediction_window(8)
-    //     .with_check_distance(opt.check_distance)
-    //     .with_input_delay(2)
-    //     .with_num_players(num_players);
-
-    // for (i, player_addr) in opt.players.iter().enumerate() {
-    //     if player_addr == "localhost" {
-    //         // local players
-    //         sess_build = sess_build.add_player(PlayerType::Local, i)?;
-    //     } else {
-    //         // remote players
-    //         let remote_addr: SocketAddr = player_addr.parse()?;
-    //         sess_build = sess_build.add_player(PlayerType::Remote(remote_addr), i)?;
-    //     }
-    // }
-
-    // for i in 0..opt.num_players {
-    //     sess_build = sess_build.add_player(PlayerType::Local, i)?;
-    // }
-
-    //let socket = UdpNonBlockingSocket::bind_to_port(opt.local_port)?;
-
-    //let sess = sess_build.start_p2p_session(socket)?;
-    //let sess = sess_build.start_synctest_session()?;
+    // Frames are only counted for the SyncTest checks
+    let ggrs_plugin = GGRSPlugin::<GGRSConfig>::new();
+    let ggrs_plugin = if opt.check_distance.is_some() {
+        ggrs_plugin.register_rollback_resource::<RollbackFrame>()
+    } else {
+        ggrs_plugin
+    };
 
-    GGRSPlugin::<GGRSConfig>::new()
+    ggrs_plugin
         .with_update_frequency(20)
         .with_input_system(bevy_fighting_lib::input::input)
         .register_rollback_component::<Transform>()
@@ -161,13 +226,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .register_rollback_component::<Facing>()
         .register_rollback_component::<GroundedHitstun>()
         .register_rollback_component::<AirborneHitstun>()
+        .register_rollback_component::<KnockedDown>()
+        .register_rollback_component::<Blockstun>()
+        .register_rollback_component::<Hitstop>()
+        .register_rollback_component::<Grabbed>()
         .register_rollback_component::<ProjectileReference>()
         .register_rollback_component::<Velocity>()
         .register_rollback_component::<PlayerAxis>()
         .register_rollback_component::<HitboxData>()
         .register_rollback_component::<Collider>()
         .register_rollback_component::<ActiveHitboxes>()
+        .register_rollback_component::<ActiveHurtboxes>()
+        .register_rollback_component::<Exclude>()
+        .register_rollback_component::<Juggle>()
+        .register_rollback_component::<Combo>()
+        .register_rollback_component::<LastCounter>()
+        .register_rollback_component::<Meter>()
         .register_rollback_component::<Owner>()
+        .register_rollback_component::<Contact>()
+        .register_rollback_component::<AbsorbedHits>()
         .register_rollback_resource::<RoundState>()
         // .with_rollback_schedule(
         //     Schedule::default()
@@ -384,6 +461,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .add_systems(
             (
                 movement_system,
+                meter_system,
+                pushbox_system,
+                stage_system,
+                wall_bounce_system,
                 axis_system,
                 object_system,
                 //fighter::animation::rollback::animation_system,
@@ -410,6 +491,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 hbox_position_system::<HitboxData>,
                 hbox_position_system::<HurtboxData>,
                 apply_system_buffers,
+                hitstop_system,
+                last_counter_system,
+                throw_system,
                 collision_system,
                 hit_event_system,
                 apply_system_buffers
@@ -421,6 +505,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Non-rollback Systems
         .add_systems((
             ui_lifebar_system, 
+            ui_combo_system,
+            ui_counter_system,
+            ui_meter_system,
             camera_system, 
             state_text_system,
             fighter::animation::rollback::animation_system
@@ -433,8 +520,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Debug Systems
         .add_system(bevy::window::close_on_esc)
         .add_system(pause_system.run_if(paused_advance_or_round))
+        .add_system(rebind_system.run_if(paused_advance_or_round))
         // Debug Resources
         .insert_resource(GameDebug(opt.debug_mode))
+        // Session Resources
+        .insert_resource(PendingSession(session))
+        .insert_resource(LocalPlayers(local_players))
         // Rollback resources
         // Custom Plugins
         .add_plugin(FighterPlugin)
@@ -443,7 +534,75 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .insert_resource(Paused(false))
         .insert_resource(Msaa::Sample2);
 
+    if opt.check_distance.is_some() {
+        app.add_plugin(SyncTestPlugin(
+            ChecksumComponents::default()
+                .track::<Transform>()
+                .track::<CurrentState>()
+                .track::<StateFrame>()
+                .track::<Health>()
+                .track_with::<Active>(|active| unordered_checksum(active.0.iter()))
+                .track::<InputBuffer>()
+                .track::<Facing>()
+                .track::<GroundedHitstun>()
+                .track::<AirborneHitstun>()
+                .track::<KnockedDown>()
+                .track::<Blockstun>()
+                .track::<Hitstop>()
+                .track::<Grabbed>()
+                .track::<ProjectileReference>()
+                .track::<Velocity>()
+                .track::<PlayerAxis>()
+                .track::<HitboxData>()
+                .track::<Collider>()
+                .track::<ActiveHitboxes>()
+                .track::<ActiveHurtboxes>()
+                .track_with::<Exclude>(|exclude| unordered_checksum(exclude.0.iter()))
+                .track::<Juggle>()
+                .track::<Combo>()
+                .track::<LastCounter>()
+                .track::<Meter>()
+                .track::<Owner>()
+                .track::<Contact>()
+                .track::<AbsorbedHits>(),
+        ));
+    }
+
     app.run();
 
     Ok(())
 }
+
+#[test]
+fn command_line_picks_the_session() {
+    let kind = |args: &[&str]| {
+        let opt = Opt::from_iter(std::iter::once("bevy_ggrs_fighting").chain(args.iter().copied()));
+        session_kind(&opt)
+    };
+
+    assert!(matches!(kind(&[]), Ok(SessionKind::Offline)));
+    // SyncTest wins over any players given
+    assert!(matches!(
+        kind(&["--synctest", "7", "--players", "localhost", "127.0.0.1:7001"]),
+        Ok(SessionKind::SyncTest(7))
+    ));
+
+    let remote: SocketAddr = "127.0.0.1:7001".parse().unwrap();
+    let spectator: SocketAddr = "10.0.0.2:7002".parse().unwrap();
+    let Ok(SessionKind::P2P(players)) = kind(&[
+        "--players",
+        "127.0.0.1:7001",
+        "localhost",
+        "--spectators",
+        "10.0.0.2:7002",
+    ]) else {
+        panic!("Expected a P2P session");
+    };
+    assert!(matches!(
+        players.as_slice(),
+        [PlayerType::Remote(a), PlayerType::Local, PlayerType::Spectator(b)] if *a == remote && *b == spectator
+    ));
+
+    assert!(kind(&["--players", "localhost"]).is_err());
+    assert!(kind(&["--players", "localhost", "not an address"]).is_err());
+}
@@ -0,0 +1,120 @@
+use bevy::log::warn;
+use bevy::prelude::{Transform, Vec3Swizzles};
+
+use crate::util::Buffer;
+
+use super::state::{Conditions, Direction, Frame, PlayerAxis, State, Velocity};
+
+/// Everything about a fighter that a transition's `Conditions` can look at
+pub struct ConditionContext<'a> {
+    pub current_id: u16,
+    pub state: &'a State,
+    pub frame: Frame,
+    pub buffer: &'a Buffer,
+    pub facing: Direction,
+    pub transform: &'a Transform,
+    pub velocity: &'a Velocity,
+    pub health: u16,
+    pub meter: u16,
+    pub axis: &'a PlayerAxis,
+    pub opponent_state: Option<u16>,
+    // whether the current state's InputWindowCheck was met, if it has one
+    pub input_met: Option<bool>,
+}
+
+impl Conditions {
+    pub fn is_met(&self, context: &ConditionContext) -> bool {
+        match self {
+            Conditions::In(list) => list.contains(&context.current_id),
+            Conditions::NotIn(id) => *id != context.current_id,
+            Conditions::Command(command) => command.compare(context.buffer, context.facing),
+            Conditions::EndDuration => {
+                context.frame > context.state.duration.expect("State doesn't have duration")
+            }
+            Conditions::Frame(window) => window.contains(context.frame),
+            Conditions::ReachGround | Conditions::Grounded => context.transform.translation.y <= 0.,
+            Conditions::Airborne => context.transform.translation.y > 0.,
+            Conditions::InputWindowCon(enact_frame) => match context.input_met {
+                Some(met) => met && context.frame >= *enact_frame,
+                None => {
+                    warn!("State {} has an InputWindowCon without an InputWindowCheck", context.current_id);
+                    false
+                }
+            },
+            Conditions::True => true,
+            Conditions::Health(threshold) => threshold.contains(context.health as f32),
+            Conditions::Meter(threshold) => threshold.contains(context.meter as f32),
+            Conditions::Distance(threshold) => threshold.contains(
+                context
+                    .transform
+                    .translation
+                    .xz()
+                    .distance(context.axis.opponent_pos.xz()),
+            ),
+            Conditions::OpponentIn(list) => context
+                .opponent_state
+                .map_or(false, |opponent_state| list.contains(&opponent_state)),
+            Conditions::Rising => context.velocity.0.y > 0.,
+            Conditions::Falling => context.velocity.0.y < 0.,
+            Conditions::Or(conditions) => conditions.iter().any(|condition| condition.is_met(context)),
+            Conditions::Nand(conditions) => !conditions.iter().all(|condition| condition.is_met(context)),
+        }
+    }
+
+    pub fn all_met(conditions: &[Conditions], context: &ConditionContext) -> bool {
+        conditions.iter().all(|condition| condition.is_met(context))
+    }
+}
+
+#[test]
+fn conditions_read_the_context() {
+    use super::state::Threshold;
+    use crate::input::BUFFER_SIZE;
+    use bevy::prelude::Vec3;
+
+    let state = State::default();
+    let buffer = Buffer::with_capacity(BUFFER_SIZE);
+    let transform = Transform::from_xyz(0., 1., 0.);
+    let velocity = Velocity(Vec3::new(0., 2., 0.));
+    let axis = PlayerAxis {
+        opponent_pos: Vec3::new(3., 0., 4.),
+        ..Default::default()
+    };
+    let context = ConditionContext {
+        current_id: 0,
+        state: &state,
+        frame: 1,
+        buffer: &buffer,
+        facing: Direction::Right,
+        transform: &transform,
+        velocity: &velocity,
+        health: 300,
+        meter: 500,
+        axis: &axis,
+        opponent_state: Some(3000),
+        input_met: None,
+    };
+    let threshold = |min, max| Threshold { min, max };
+
+    assert!(Conditions::Health(threshold(Some(300.), None)).is_met(&context));
+    assert!(!Conditions::Meter(threshold(None, Some(499.))).is_met(&context));
+    // Distance is along the floor, so the height doesn't count
+    assert!(Conditions::Distance(threshold(Some(5.), Some(5.))).is_met(&context));
+    assert!(Conditions::OpponentIn(vec![3000, 3001].into()).is_met(&context));
+    assert!(!Conditions::OpponentIn(3010.into()).is_met(&context));
+    assert!(Conditions::Airborne.is_met(&context));
+    assert!(!Conditions::Grounded.is_met(&context));
+    assert!(Conditions::Rising.is_met(&context));
+    assert!(!Conditions::Falling.is_met(&context));
+
+    assert!(Conditions::Or(vec![Conditions::Falling, Conditions::Rising]).is_met(&context));
+    assert!(!Conditions::Or(vec![Conditions::Falling, Conditions::Grounded]).is_met(&context));
+    assert!(!Conditions::Nand(vec![Conditions::Rising]).is_met(&context));
+    assert!(Conditions::Nand(vec![Conditions::Falling]).is_met(&context));
+    // Only fails when every condition is met
+    assert!(Conditions::Nand(vec![Conditions::Rising, Conditions::Falling]).is_met(&context));
+    assert!(!Conditions::Nand(vec![Conditions::Rising, Conditions::Airborne]).is_met(&context));
+
+    // Without the state's InputWindowCheck there's nothing to have met
+    assert!(!Conditions::InputWindowCon(1).is_met(&context));
+}
@@ -2,7 +2,7 @@ use bevy::gltf::Gltf;
 use bevy::prelude::{Entity, Handle, ResMut, Resource, Vec3, Plugin, IntoSystemConfig, in_state};
 
 use bevy::ecs::reflect::ReflectComponent;
-use bevy::reflect::{Struct, TypeUuid};
+use bevy::reflect::{FromReflect, Struct, TypeUuid};
 use bevy::utils::HashMap;
 use bevy::{prelude::Component, reflect::Reflect};
 
@@ -11,9 +11,14 @@ use parry3d::shape::{Capsule, Segment};
 use serde::{Deserialize, Serialize};
 
 use crate::game::NewGameState;
+use crate::input::CommandInput;
 
-use super::hit::components::HitboxData;
-use super::state::{HurtboxData, SerializedStateVec};
+use super::hit::components::{CounterHit, HitboxData};
+use super::state::{
+    HurtboxData, Pushbox, SerializedStateVec, AIR_HITSTUN, CROUCH_BLOCKSTUN, CROUCH_HITSTUN, DIZZY,
+    GRND_HITSTUN_KB, STAND_BLOCKSTUN, THROWN, THROW_TECH, HARD_KNOCKDOWN, SOFT_KNOCKDOWN,
+    QUICK_RISE, BACK_RISE,
+};
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize, Component, Reflect, TypeUuid)]
 #[reflect(Component)]
@@ -29,11 +34,143 @@ pub struct FighterData {
     pub walk_forward: f32,
     #[serde(alias = "walkBack", default)]
     pub walk_back: f32,
+    #[serde(alias = "hitReactions", default)]
+    pub hit_reactions: HitReactions,
+    // Used by states without their own pushbox
+    #[serde(default)]
+    pub pushbox: Pushbox,
+    // How far from the fighter's position throws can grab it
+    #[serde(alias = "throwableRadius", default = "FighterData::throwable_radius_default")]
+    pub throwable_radius: f32,
+    // Juggle points the fighter can take in the air before it can't be launched again
+    #[serde(alias = "juggleLimit", default = "FighterData::juggle_limit_default")]
+    pub juggle_limit: u8,
+    #[serde(alias = "comboScaling", default)]
+    pub combo_scaling: ComboScaling,
+    #[serde(alias = "meterMax", default = "FighterData::meter_max_default")]
+    pub meter_max: u16,
+    #[serde(alias = "meterGain", default)]
+    pub meter_gain: MeterGain,
+    #[serde(default)]
+    pub wakeup: Wakeup,
 
     #[serde(flatten)]
     pub extra: HashMap<String, u8>,
 }
 
+impl FighterData {
+    fn throwable_radius_default() -> f32 {
+        0.4
+    }
+
+    fn juggle_limit_default() -> u8 {
+        6
+    }
+
+    fn meter_max_default() -> u16 {
+        1000
+    }
+}
+
+/// The states a fighter is put in when getting hit or blocking
+#[derive(Clone, Debug, Serialize, Deserialize, Reflect, FromReflect)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HitReactions {
+    pub stand: u16,
+    pub crouch: u16,
+    pub air: u16,
+    pub dizzy: u16,
+    pub stand_block: u16,
+    pub crouch_block: u16,
+    pub thrown: u16,
+    pub throw_tech: u16,
+    pub hard_knockdown: u16,
+    pub soft_knockdown: u16,
+    pub quick_rise: u16,
+    pub back_rise: u16,
+    // Knockback of a grounded only hit on an airborne fighter
+    pub air_reset: Vec3,
+}
+
+impl Default for HitReactions {
+    fn default() -> Self {
+        Self {
+            stand: GRND_HITSTUN_KB,
+            crouch: CROUCH_HITSTUN,
+            air: AIR_HITSTUN,
+            dizzy: DIZZY,
+            stand_block: STAND_BLOCKSTUN,
+            crouch_block: CROUCH_BLOCKSTUN,
+            thrown: THROWN,
+            throw_tech: THROW_TECH,
+            hard_knockdown: HARD_KNOCKDOWN,
+            soft_knockdown: SOFT_KNOCKDOWN,
+            quick_rise: QUICK_RISE,
+            back_rise: BACK_RISE,
+            air_reset: Vec3::new(1., 3., 0.),
+        }
+    }
+}
+
+/// How the damage of each hit in a combo is scaled down, as fractions of its damage
+#[derive(Clone, Debug, Serialize, Deserialize, Reflect, FromReflect)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ComboScaling {
+    // Taken off for every hit of the combo past the second
+    pub per_hit: f32,
+    // The least a hit is scaled to
+    pub minimum: f32,
+}
+
+impl Default for ComboScaling {
+    fn default() -> Self {
+        Self {
+            per_hit: 0.1,
+            minimum: 0.2,
+        }
+    }
+}
+
+/// Meter the fighter gains. Hitboxes can override what they give their owner.
+#[derive(Clone, Debug, Serialize, Deserialize, Reflect, FromReflect)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MeterGain {
+    pub hit: u16,
+    pub block: u16,
+    // when the fighter is hit
+    pub taken: u16,
+    // every frame of walking forward
+    pub walk_forward: u16,
+}
+
+impl Default for MeterGain {
+    fn default() -> Self {
+        Self {
+            hit: 20,
+            block: 10,
+            taken: 10,
+            walk_forward: 0,
+        }
+    }
+}
+
+/// The commands that get the fighter up early from a soft knockdown
+#[derive(Clone, Debug, Serialize, Deserialize, Reflect, FromReflect)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Wakeup {
+    pub quick_rise: CommandInput,
+    pub back_rise: CommandInput,
+}
+
+impl Default for Wakeup {
+    fn default() -> Self {
+        Self {
+            quick_rise: CommandInput::from_notation("J").expect("Quick rise notation doesn't compile"),
+            back_rise: CommandInput::from_notation("4J").expect("Back rise notation doesn't compile"),
+        }
+    }
+}
+
 // impl DynamicAsset for FighterData {
 //     fn load(&self, asset_server: &bevy::prelude::AssetServer) -> Vec<bevy::prelude::HandleUntyped> {
 //         todo!()
@@ -93,7 +230,7 @@ pub struct FighterAssets {
     p1_model: Handle<Gltf>,
 }
 
-#[derive(Component, Clone, Reflect)]
+#[derive(Debug, Component, Clone, Reflect)]
 pub struct Collider {
     pub radius: f32,
     pub segment: SegmentProxy,
@@ -124,7 +261,7 @@ impl From<&Collider> for Capsule {
     }
 }
 
-#[derive(Clone, Reflect)]
+#[derive(Debug, Clone, Reflect)]
 pub struct SegmentProxy {
     pub a: Vec3,
     pub b: Vec3,
@@ -154,11 +291,26 @@ impl From<Segment> for SegmentProxy {
     }
 }
 
+/// Whether a hit caught the recipient in the middle of its own attack
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, FromReflect, Reflect)]
+pub enum CounterKind {
+    #[default]
+    None,
+    // During the startup or active frames
+    Counter,
+    // During the recovery
+    Punish,
+}
+
+#[derive(Clone)]
 pub struct CollisionData {
     pub attacker_box: HitboxData,
     pub attacker: Entity,
+    // The projectile that hit, if it wasn't one of the attacker's own hitboxes
+    pub projectile: Option<Entity>,
     pub recipient_box: HurtboxData,
     pub recipient: Entity,
+    pub counter: CounterKind,
 }
 
 impl CollisionData {
@@ -169,6 +321,17 @@ impl CollisionData {
     pub fn get_recipient(&self) -> Entity {
         self.recipient
     }
+
+    pub fn counter_hit(&self) -> Option<&CounterHit> {
+        match self.counter {
+            CounterKind::None => None,
+            CounterKind::Counter => Some(&self.attacker_box.counter_hit),
+            CounterKind::Punish => Some(&self.attacker_box.punish_counter),
+        }
+    }
 }
 
 pub struct HitEvent(pub CollisionData);
+
+/// Sent instead of the hit landing when the recipient guarded it
+pub struct BlockEvent(pub CollisionData);
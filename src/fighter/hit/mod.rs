@@ -7,7 +7,15 @@ pub mod components {
         Deserialize, Serialize,
     };
 
-    use super::super::state::{Frame, FrameWindow, HBox, HitLevel};
+    use super::super::state::{Frame, FrameWindow, HBox, HitLevel, ProjectileData};
+    use crate::input::CommandInput;
+
+    // Chip damage of a blocked hit without its own is damage / CHIP_DIVISOR
+    const CHIP_DIVISOR: u16 = 8;
+    // Extra hitstun for crouching fighters when a hit doesn't give its own
+    pub const CROUCH_HITSTUN_BONUS: Frame = 2;
+    // Frames a fighter stays down after a hit without its own knockdown
+    pub const SOFT_KNOCKDOWN_FRAMES: Frame = 24;
 
     #[derive(Default, Debug, Serialize, Deserialize, Clone, FromReflect, Reflect, Component)]
     #[reflect(Component)]
@@ -31,14 +39,49 @@ pub mod components {
         pub damage: u16,
         #[serde(alias = "onHit", default)]
         pub on_hit: OnHit,
+        // Set to null, the hit is grounded only and resets fighters hit in the air
         #[serde(alias = "onAirHit", default = "HitboxData::on_air_hit_default")]
-        pub on_air_hit: OnHit,
+        pub on_air_hit: Option<OnHit>,
         pub blockstun: Frame,
+        #[serde(alias = "chipDamage", default)]
+        pub chip_damage: Option<u16>,
+        #[serde(alias = "blockPushback", default = "HitboxData::block_pushback_default")]
+        pub block_pushback: f32,
+        #[serde(alias = "attackerHitstop", default = "HitboxData::hitstop_default")]
+        pub attacker_hitstop: Frame,
+        #[serde(alias = "defenderHitstop", default = "HitboxData::hitstop_default")]
+        pub defender_hitstop: Frame,
         window: FrameWindow,
         #[serde(default)]
         rehit: Option<u16>, // Number frames after hitting that hitbox can hit again,
+        // Hitting with a hitbox keeps the rest of its group from hitting too
+        #[serde(default)]
+        pub group: u8,
         #[serde(alias = "hitLevel", default)]
         hit_level: HitLevel,
+        // Juggle points the hit takes from a fighter it launches or hits in the air
+        #[serde(default = "HitboxData::juggle_default")]
+        pub juggle: u8,
+        // Scaling for the rest of the combo this hit starts, on top of the usual scaling
+        #[serde(alias = "starterScaling", default)]
+        pub starter_scaling: Option<f32>,
+        // Meter its owner gains when the hit lands or is blocked, instead of the usual
+        #[serde(alias = "meterGain", default)]
+        pub meter_gain: Option<u16>,
+        #[serde(alias = "blockMeterGain", default)]
+        pub block_meter_gain: Option<u16>,
+        #[serde(alias = "counterHit", default = "CounterHit::counter_default")]
+        pub counter_hit: CounterHit,
+        #[serde(alias = "punishCounter", default = "CounterHit::punish_default")]
+        pub punish_counter: CounterHit,
+        // How a fighter it sent airborne is knocked down on landing
+        #[serde(default)]
+        pub knockdown: Knockdown,
+        // Velocity a fighter it sent airborne bounces with, off the ground or a wall
+        #[serde(alias = "groundBounce", default)]
+        pub ground_bounce: Option<Vec3>,
+        #[serde(alias = "wallBounce", default)]
+        pub wall_bounce: Option<Vec3>,
     }
 
     impl HitboxData {
@@ -53,11 +96,70 @@ pub mod components {
                 .start
                 .expect("End frame does not exist in HitboxData")
         }
+
+        pub fn get_hit_level(&self) -> &HitLevel {
+            &self.hit_level
+        }
+
+        pub fn get_rehit(&self) -> Option<Frame> {
+            self.rehit
+        }
+
+        pub fn get_chip_damage(&self) -> u16 {
+            self.chip_damage.unwrap_or(self.damage / CHIP_DIVISOR)
+        }
     }
 
     impl HitboxData {
-        fn on_air_hit_default() -> OnHit {
-            OnHit::Launch(Vec3::default())
+        fn block_pushback_default() -> f32 {
+            2.
+        }
+
+        fn hitstop_default() -> Frame {
+            8
+        }
+
+        fn juggle_default() -> u8 {
+            1
+        }
+
+        /// The hit a projectile deals, so it can be handled like any other hitbox
+        pub fn from_projectile(projectile: &ProjectileData) -> Self {
+            HitboxData {
+                priority: projectile.priority,
+                radius: projectile.dimensions.x / 2.,
+                half_height: projectile.dimensions.y / 2.,
+                damage: projectile.damage,
+                on_hit: projectile.on_hit.clone(),
+                blockstun: projectile.blockstun,
+                block_pushback: HitboxData::block_pushback_default(),
+                defender_hitstop: HitboxData::hitstop_default(),
+                window: FrameWindow {
+                    start: Some(1),
+                    end: Some(projectile.life_frames),
+                },
+                hit_level: projectile.hit_level.clone(),
+                rehit: projectile.rehit,
+                juggle: HitboxData::juggle_default(),
+                counter_hit: CounterHit::counter_default(),
+                punish_counter: CounterHit::punish_default(),
+                ..Default::default()
+            }
+        }
+
+        /// The hit dealt once a throw can't be teched anymore. It can't be blocked.
+        pub fn from_throw(throw: &ThrowboxData) -> Self {
+            HitboxData {
+                damage: throw.damage,
+                on_hit: throw.on_hit.clone(),
+                on_air_hit: Some(throw.on_hit.clone()),
+                hit_level: HitLevel::Unblockable,
+                ..Default::default()
+            }
+        }
+
+        fn on_air_hit_default() -> Option<OnHit> {
+            Some(OnHit::Launch(Vec3::default()))
         }
 
         pub fn mesh_default() -> Option<Handle<Mesh>> {
@@ -87,19 +189,133 @@ pub mod components {
     #[derive(Debug, Serialize, Deserialize, Clone, FromReflect, Reflect, Component)]
     pub enum OnHit {
         Launch(Vec3),
-        Grounded { kb: Vec3, hitstun: Frame },
+        Grounded {
+            kb: Vec3,
+            hitstun: Frame,
+            #[serde(alias = "crouchHitstun", default)]
+            crouch_hitstun: Option<Frame>,
+        },
+        // Dizzies the fighter for this many frames
         Stun(Frame)
     }
 
     impl Default for OnHit {
         fn default() -> Self {
-            Self::Grounded { kb: Vec3::default(), hitstun: 1 }
+            Self::Grounded { kb: Vec3::default(), hitstun: 1, crouch_hitstun: None }
+        }
+    }
+
+    /// What changes when a hit lands as a counter hit or punish counter
+    #[derive(Default, Debug, Serialize, Deserialize, Clone, FromReflect, Reflect)]
+    #[serde(rename_all = "camelCase", default)]
+    pub struct CounterHit {
+        pub extra_hitstun: Frame,
+        // Fraction of the hit's damage that's added to it
+        pub extra_damage: f32,
+        // Replaces the hit's usual reaction on grounded fighters
+        pub on_hit: Option<OnHit>,
+    }
+
+    impl CounterHit {
+        fn counter_default() -> Self {
+            CounterHit {
+                extra_hitstun: 4,
+                extra_damage: 0.1,
+                on_hit: None,
+            }
+        }
+
+        fn punish_default() -> Self {
+            CounterHit {
+                extra_hitstun: 6,
+                extra_damage: 0.2,
+                on_hit: None,
+            }
+        }
+
+        pub fn damage(&self, damage: u16) -> u16 {
+            damage.saturating_add((damage as f32 * self.extra_damage).round() as u16)
         }
     }
 
+    /// A knockdown and the frames the fighter stays down for
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, FromReflect, Reflect)]
+    pub enum Knockdown {
+        // The fighter can wake up early with a quick rise or back rise
+        Soft(Frame),
+        Hard(Frame),
+    }
+
+    impl Default for Knockdown {
+        fn default() -> Self {
+            Self::Soft(SOFT_KNOCKDOWN_FRAMES)
+        }
+    }
+
+    impl Knockdown {
+        pub fn duration(&self) -> Frame {
+            match *self {
+                Knockdown::Soft(frames) | Knockdown::Hard(frames) => frames,
+            }
+        }
+    }
+
+    /// How the hit that sent the fighter airborne ends once it reaches the ground or a wall
     #[derive(Serialize, Deserialize, Default, Debug, Component, Reflect, Clone)]
     #[reflect(Component)]
-    pub struct AirborneHitstun;
+    pub struct AirborneHitstun {
+        // Air resets land on their feet
+        pub knockdown: Option<Knockdown>,
+        pub ground_bounce: Option<Vec3>,
+        pub wall_bounce: Option<Vec3>,
+    }
+
+    impl AirborneHitstun {
+        pub fn from_hit(hitbox: &HitboxData) -> Self {
+            AirborneHitstun {
+                knockdown: Some(hitbox.knockdown),
+                ground_bounce: hitbox.ground_bounce,
+                wall_bounce: hitbox.wall_bounce,
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Default, Debug, Component, Reflect, Clone)]
+    #[reflect(Component)]
+    pub struct KnockedDown(pub Knockdown);
+
+    /// A grab, which only connects with the opponent's throwable radius and can't be blocked.
+    /// Ignores opponents in hitstun or blockstun, and airborne ones unless it's an air throw.
+    #[derive(Default, Debug, Serialize, Deserialize, Clone, FromReflect, Reflect)]
+    #[serde(rename_all = "camelCase")]
+    pub struct ThrowboxData {
+        pub window: FrameWindow,
+        // How far from the thrower the throw reaches
+        pub range: f32,
+        // Air throws only grab airborne opponents
+        #[serde(default)]
+        pub air: bool,
+        // The thrower's state once the throw connects
+        pub state: u16,
+        pub damage: u16,
+        #[serde(default)]
+        pub on_hit: OnHit,
+        // Frames the thrown fighter has to input the tech
+        #[serde(default = "ThrowboxData::tech_window_default")]
+        pub tech_window: Frame,
+        #[serde(default = "ThrowboxData::tech_default")]
+        pub tech: CommandInput,
+    }
+
+    impl ThrowboxData {
+        fn tech_window_default() -> Frame {
+            7
+        }
+
+        fn tech_default() -> CommandInput {
+            CommandInput::from_notation("AJ").expect("Tech notation doesn't compile")
+        }
+    }
 }
 
 
@@ -6,8 +6,8 @@ use bevy_mod_scripting::lua::api::RegisterForeignLuaType;
 
 
 use self::{
-    data::{FighterData, HitEvent}, 
-    state::{StateMap, CurrentState, State as FightState, StateFrame, Facing, Health, Direction, ProjectileReference, Exclude, Velocity, Hurtboxes, PlayerAxis}, 
+    data::{BlockEvent, FighterData, HitEvent}, 
+    state::{StateMap, Blockstun, Juggle, Combo, LastCounter, Meter, Contact, AbsorbedHits, Grabbed, Hitstop, CurrentState, State as FightState, StateFrame, Facing, Health, Direction, ProjectileReference, Exclude, Velocity, Hurtboxes, ActiveHurtboxes, PlayerAxis}, 
     systems::InputBuffer, event::TransitionEvent,
     modifiers::{Movement, AdjustFacing, CreateObject, Velo, OnExitSetPos, InputWindowCheck, InputMet, OnExitZeroVelo}, hit::components::HitboxData, animation::components::{BoneTransforms, TransformListRef}
 };
@@ -19,6 +19,7 @@ pub mod event;
 pub mod modifiers;
 pub mod animation;
 pub mod hit;
+pub mod conditions;
 
 #[derive(Component)]
 pub struct Fighter;
@@ -30,6 +31,7 @@ impl Plugin for FighterPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_event::<HitEvent>()
+            .add_event::<BlockEvent>()
             .add_event::<TransitionEvent>()
 
             // Modifiers
@@ -47,6 +49,15 @@ impl Plugin for FighterPlugin {
             .register_type::<InputBuffer>()
             .register_type::<ProjectileReference>()
             .register_type::<Exclude>()
+            .register_type::<Contact>()
+            .register_type::<Juggle>()
+            .register_type::<Combo>()
+            .register_type::<LastCounter>()
+            .register_type::<Meter>()
+            .register_type::<AbsorbedHits>()
+            .register_type::<Blockstun>()
+            .register_type::<Hitstop>()
+            .register_type::<Grabbed>()
 
             // These registers below are purely for the inspector
             .register_type::<CurrentState>()
@@ -58,6 +69,7 @@ impl Plugin for FighterPlugin {
             .register_type::<InputMet>()
             .register_type::<Velocity>()
             .register_type::<Hurtboxes>()
+            .register_type::<ActiveHurtboxes>()
             .register_type::<PlayerAxis>()
 
             //.register_foreign_lua_type::<Velocity>()
@@ -16,8 +16,9 @@ use serde_json::{from_value, Number};
 
 //use bevy_editor_pls::default_windows::inspector::InspectorWindow;
 
-use crate::fighter::hit::components::HitboxData;
-use crate::input::{CommandInput, MatchExpression};
+use crate::fighter::data::{ComboScaling, CounterKind};
+use crate::fighter::hit::components::{HitboxData, OnHit, ThrowboxData};
+use crate::input::{CommandInput, DirectionalInput, MatchExpression, StateInput};
 
 use super::modifiers::StateModifier;
 
@@ -26,6 +27,17 @@ pub type Frame = u16;
 pub const GRND_HITSTUN_KB: u16 = 3000;
 pub const GRND_HITSTUN: u16 = 3001;
 pub const AIR_HITSTUN: u16 = 3002;
+pub const CROUCH_HITSTUN: u16 = 3003;
+pub const DIZZY: u16 = 3020;
+pub const THROWN: u16 = 3030;
+pub const THROW_TECH: u16 = 3040;
+pub const HARD_KNOCKDOWN: u16 = 3050;
+pub const SOFT_KNOCKDOWN: u16 = 3051;
+pub const QUICK_RISE: u16 = 3060;
+pub const BACK_RISE: u16 = 3061;
+pub const FORWARD_WALK: u16 = 5;
+pub const STAND_BLOCKSTUN: u16 = 3010;
+pub const CROUCH_BLOCKSTUN: u16 = 3011;
 
 #[derive(Default, Debug, Serialize, Deserialize, Component, Reflect)]
 #[reflect(Component)]
@@ -56,10 +68,30 @@ pub struct State {
     pub name: String,
     pub duration: Option<u16>,
     pub hitboxes: Option<HashMap<u16, Vec<HitboxData>>>,
-    pub hurtboxes: Option<HashMap<u16, HashSet<Entity>>>,
+    pub hurtboxes: Option<HashMap<u16, Vec<HurtboxData>>>,
+    // The model's own hurtboxes are ignored, leaving only the state's
+    pub replace_hurtboxes: bool,
     pub transitions: Vec<Entity>,
     pub triggers: (Option<Vec<Conditions>>, Vec<Vec<Conditions>>),
     pub height: StateHeight,
+    pub groups: Vec<String>,
+    pub cancels: Vec<CancelRoute>,
+    pub pushbox: Option<Pushbox>,
+    pub throwboxes: Vec<ThrowboxData>,
+    pub invulnerability: Vec<Invulnerability>,
+    pub armor: Option<Armor>,
+    // Meter spent when entering the state
+    pub meter_cost: u16,
+    // Without them, the active frames are those of the hitboxes
+    pub active_frames: Option<FrameWindow>,
+}
+
+/// The part of an attack a state is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Startup,
+    Active,
+    Recovery,
 }
 
 impl State {
@@ -70,17 +102,72 @@ impl State {
             duration: serialized.duration,
             hitboxes: None,
             hurtboxes: None,
+            replace_hurtboxes: serialized.replace_hurtboxes,
             transitions: Vec::new(),
             triggers: serialized.triggers,
             height: serialized.height,
+            groups: serialized.groups,
+            cancels: Vec::new(),
+            pushbox: serialized.pushbox,
+            throwboxes: serialized.throwboxes,
+            invulnerability: serialized.invulnerability,
+            armor: serialized.armor,
+            meter_cost: serialized.meter_cost,
+            active_frames: serialized.active_frames,
         }
     }
 
+    /// The part of the attack the frame is in, for states that attack
+    pub fn phase(&self, frame: Frame) -> Option<Phase> {
+        let active = self.active_frames.clone().or_else(|| {
+            let first = *self.hitboxes.as_ref()?.keys().min()?;
+            Some([first, self.last_active_frame()?].into())
+        })?;
+
+        if active.start.map_or(false, |start| frame < start) {
+            Some(Phase::Startup)
+        } else if active.contains(frame) {
+            Some(Phase::Active)
+        } else {
+            Some(Phase::Recovery)
+        }
+    }
+
+    pub fn strike_invulnerable(&self, frame: Frame) -> bool {
+        self.invulnerability
+            .iter()
+            .any(|invuln| invuln.to != InvulnerableTo::Throws && invuln.window.contains(frame))
+    }
+
+    pub fn throw_invulnerable(&self, frame: Frame) -> bool {
+        self.invulnerability
+            .iter()
+            .any(|invuln| invuln.to != InvulnerableTo::Strikes && invuln.window.contains(frame))
+    }
+
+    // How many hits the state's armor absorbs on this frame
+    pub fn armor_hits(&self, frame: Frame) -> u8 {
+        self.armor
+            .as_ref()
+            .filter(|armor| armor.window.contains(frame))
+            .map_or(0, |armor| armor.hits)
+    }
+
+    // The last frame any of the state's hitboxes are out
+    pub fn last_active_frame(&self) -> Option<Frame> {
+        self.hitboxes
+            .as_ref()?
+            .values()
+            .flatten()
+            .map(|hitbox| hitbox.get_end_frame())
+            .max()
+    }
+
     pub fn add_hitboxes(&mut self, hitboxes: HashMap<u16, Vec<HitboxData>>) {
         self.hitboxes = Some(hitboxes);
     }
 
-    pub fn add_hurtboxes(&mut self, hurtboxes: HashMap<u16, HashSet<Entity>>) {
+    pub fn add_hurtboxes(&mut self, hurtboxes: HashMap<u16, Vec<HurtboxData>>) {
         self.hurtboxes = Some(hurtboxes);
     }
 }
@@ -99,9 +186,154 @@ pub enum Conditions {
     ReachGround,
     // hitbox id (optional), frame range cancel
     //OnHit(Option<usize>, u16)
+    // the state's InputWindowCheck was met, and the current frame is at least this one
     InputWindowCon(u16),
     // always returns true
-    True
+    True,
+    Health(Threshold),
+    Meter(Threshold),
+    // horizontal distance to the opponent
+    Distance(Threshold),
+    // the opponent's current state
+    OpponentIn(StateList),
+    Airborne,
+    Grounded,
+    // vertical velocity
+    Rising,
+    Falling,
+    // any of the conditions
+    Or(Vec<Conditions>),
+    // met unless all of the conditions are, so with a single condition it's a plain not
+    Nand(Vec<Conditions>),
+}
+
+/// Inclusive bounds on a value, either of which can be left out
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Reflect, FromReflect)]
+pub struct Threshold {
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+}
+
+impl Threshold {
+    pub fn contains(&self, value: f32) -> bool {
+        self.min.map_or(true, |min| value >= min) && self.max.map_or(true, |max| value <= max)
+    }
+}
+
+/// What the hitboxes of a state connected with. Only counts while the fighter is still in that state.
+#[derive(Default, Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct Contact {
+    pub state: Option<Entity>,
+    pub kind: ContactKind,
+}
+
+impl Contact {
+    pub fn in_state(&self, state: Entity) -> ContactKind {
+        if self.state == Some(state) {
+            self.kind
+        } else {
+            ContactKind::None
+        }
+    }
+}
+
+/// Hits absorbed by the armor of a state. Only counts while the fighter is still in that state.
+#[derive(Default, Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct AbsorbedHits {
+    pub state: Option<Entity>,
+    pub hits: u8,
+}
+
+impl AbsorbedHits {
+    pub fn in_state(&self, state: Entity) -> u8 {
+        if self.state == Some(state) {
+            self.hits
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq, FromReflect, Reflect)]
+#[serde(rename_all = "camelCase")]
+pub enum InvulnerableTo {
+    #[default]
+    All,
+    Strikes,
+    Throws,
+}
+
+/// Frames of a state where hits, throws or both pass through the fighter
+#[derive(Serialize, Deserialize, Clone, Debug, Default, FromReflect, Reflect)]
+pub struct Invulnerability {
+    pub window: FrameWindow,
+    #[serde(default)]
+    pub to: InvulnerableTo,
+}
+
+/// Frames of a state where up to `hits` hits only deal damage, without a hit reaction
+#[derive(Serialize, Deserialize, Clone, Debug, Default, FromReflect, Reflect)]
+pub struct Armor {
+    pub window: FrameWindow,
+    pub hits: u8,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, FromReflect, Reflect)]
+pub enum ContactKind {
+    #[default]
+    None,
+    Hit,
+    Block,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, FromReflect, Reflect)]
+#[serde(rename_all = "camelCase")]
+pub enum CancelOn {
+    Hit,
+    Block,
+    // the state's hitboxes are all over without connecting
+    Whiff,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum CancelTarget {
+    Id(u16),
+    // every state with this group, e.g. "special"
+    Group(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SerializedCancelRoute {
+    pub on: Vec<CancelOn>,
+    pub targets: Vec<CancelTarget>,
+    #[serde(default)]
+    pub window: FrameWindow,
+}
+
+/// States that can be canceled into once the current state's hitboxes hit, are blocked or whiff.
+/// The target's triggers still have to be met.
+#[derive(Clone, Debug, Default, FromReflect, Reflect)]
+pub struct CancelRoute {
+    pub on: Vec<CancelOn>,
+    pub window: FrameWindow,
+    pub targets: Vec<Entity>,
+}
+
+impl CancelRoute {
+    pub fn is_open(&self, contact: ContactKind, whiffed: bool, frame: Frame) -> bool {
+        let connected = match contact {
+            ContactKind::Hit => self.on.contains(&CancelOn::Hit),
+            ContactKind::Block => self.on.contains(&CancelOn::Block),
+            ContactKind::None => whiffed && self.on.contains(&CancelOn::Whiff),
+        };
+
+        connected && self.window.contains(frame)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, FromReflect, Reflect)]
@@ -169,6 +401,7 @@ pub struct SerializedState {
     duration: Option<u16>,
     pub unsorted_hitboxes: Option<Vec<HitboxData>>,
     pub unsorted_hurtboxes: Option<Vec<HurtboxData>>,
+    pub replace_hurtboxes: bool,
     pub modifiers: Option<Vec<Box<dyn StateModifier>>>,
     pub transitions: Vec<u16>,
     pub triggers: (Option<Vec<Conditions>>, Vec<Vec<Conditions>>),
@@ -176,6 +409,14 @@ pub struct SerializedState {
     pub active_type: ActiveOrPassive,
     pub scripts: Option<Vec<String>>,
     pub on_enter: Vec<Option<Box<dyn StateModifier>>>,
+    pub groups: Vec<String>,
+    pub cancels: Vec<SerializedCancelRoute>,
+    pub pushbox: Option<Pushbox>,
+    pub throwboxes: Vec<ThrowboxData>,
+    pub invulnerability: Vec<Invulnerability>,
+    pub armor: Option<Armor>,
+    pub meter_cost: u16,
+    pub active_frames: Option<FrameWindow>,
 }
 
 impl<'de> Deserialize<'de> for SerializedState {
@@ -192,6 +433,7 @@ impl<'de> Deserialize<'de> for SerializedState {
         let mut duration: Option<u16> = None;
         let mut unsorted_hitboxes: Option<Vec<HitboxData>> = None;
         let mut unsorted_hurtboxes: Option<Vec<HurtboxData>> = None;
+        let mut replace_hurtboxes = false;
         let mut modifiers: Option<Vec<Box<dyn StateModifier>>> = None;
         let mut transitions: Vec<u16> = vec![0];
         let mut triggers: (Option<Vec<Conditions>>, Vec<Vec<Conditions>>) = (None, Vec::new());
@@ -199,6 +441,14 @@ impl<'de> Deserialize<'de> for SerializedState {
         let mut active_type: ActiveOrPassive = ActiveOrPassive::default();
         let mut scripts: Option<Vec<String>> = None;
         let mut on_enter: Vec<Option<Box<dyn StateModifier>>> = vec![];
+        let mut groups: Vec<String> = Vec::new();
+        let mut cancels: Vec<SerializedCancelRoute> = Vec::new();
+        let mut pushbox: Option<Pushbox> = None;
+        let mut throwboxes: Vec<ThrowboxData> = Vec::new();
+        let mut invulnerability: Vec<Invulnerability> = Vec::new();
+        let mut armor: Option<Armor> = None;
+        let mut meter_cost: u16 = 0;
+        let mut active_frames: Option<FrameWindow> = None;
 
         for (key, value) in object.iter_mut() {
             let key = key.as_str();
@@ -220,6 +470,8 @@ impl<'de> Deserialize<'de> for SerializedState {
                 unsorted_hurtboxes = Some(
                     from_value(value.clone()).expect("Can't convert array to Vec<HurtboxData>"),
                 );
+            } else if key == "replaceHurtboxes" {
+                replace_hurtboxes = value.as_bool().expect("bool");
             } else if key == "modifiers" {
                 modifiers = Some(
                     from_value(value.take())
@@ -237,6 +489,25 @@ impl<'de> Deserialize<'de> for SerializedState {
             } else if key == "onEnter" {
                 on_enter = from_value(value.take())
                     .expect("Can't convert array to Vec<Option<Box<dyn StateModifier>>>")
+            } else if key == "groups" {
+                groups = from_value(value.clone()).expect("Can't convert array to Vec<String>");
+            } else if key == "cancels" {
+                cancels = from_value(value.take())
+                    .expect("Can't convert array to Vec<SerializedCancelRoute>");
+            } else if key == "pushbox" {
+                pushbox = from_value(value.clone()).expect("Can't convert to Pushbox");
+            } else if key == "throwboxes" {
+                throwboxes = from_value(value.take())
+                    .expect("Can't convert array to Vec<ThrowboxData>");
+            } else if key == "invulnerability" {
+                invulnerability = from_value(value.take())
+                    .expect("Can't convert array to Vec<Invulnerability>");
+            } else if key == "armor" {
+                armor = from_value(value.clone()).expect("Can't convert to Armor");
+            } else if key == "meterCost" {
+                meter_cost = value.as_u64().expect("u64") as u16;
+            } else if key == "activeFrames" {
+                active_frames = from_value(value.clone()).expect("Can't convert to FrameWindow");
             } else if key == "triggerAll" {
                 triggers.0 = Some(
                     from_value(value.take()).expect("Can't convert array to Vec<Conditions>"),
@@ -254,6 +525,7 @@ impl<'de> Deserialize<'de> for SerializedState {
             duration,
             unsorted_hitboxes,
             unsorted_hurtboxes,
+            replace_hurtboxes,
             modifiers,
             transitions,
             triggers,
@@ -261,6 +533,14 @@ impl<'de> Deserialize<'de> for SerializedState {
             active_type,
             scripts,
             on_enter,
+            groups,
+            cancels,
+            pushbox,
+            throwboxes,
+            invulnerability,
+            armor,
+            meter_cost,
+            active_frames,
         })
     }
 }
@@ -283,6 +563,24 @@ pub enum HitLevel {
     Middle,
     #[default]
     High,
+    Unblockable,
+}
+
+impl HitLevel {
+    /// Whether holding this input blocks the hit. The input has to be relative to the defender's facing.
+    pub fn blocked_by(&self, input: &StateInput) -> bool {
+        if input.x != DirectionalInput::Negative {
+            return false;
+        }
+
+        let crouching = input.y == DirectionalInput::Negative;
+        match self {
+            HitLevel::Low => crouching,
+            HitLevel::Middle => true,
+            HitLevel::High => !crouching,
+            HitLevel::Unblockable => false,
+        }
+    }
 }
 
 pub fn deserialize_rotation<'de, D>(deserializer: D) -> Result<(f32, f32), D::Error>
@@ -318,9 +616,66 @@ where
 #[derive(Component)]
 pub struct BoneMap(pub HashMap<String, Entity>);
 
-#[derive(Component, Reflect, Default)]
+#[derive(Debug, Component, Reflect, Default)]
 pub struct ActiveHitboxes(pub Vec<Entity>);
 
+/// Hurtboxes spawned from the state the fighter was in, which are despawned when it leaves.
+/// They're kept by global ID, since the spawned entities don't survive a rollback.
+#[derive(Debug, Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct ActiveHurtboxes {
+    pub state: Option<Entity>,
+    pub list: Vec<u32>,
+}
+
+/// The body of a fighter, which keeps it from walking through the other one.
+/// It stands on the fighter's position and is centered on it.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, FromReflect, Reflect)]
+pub struct Pushbox {
+    #[serde(alias = "halfWidth")]
+    pub half_width: f32,
+    pub height: f32,
+}
+
+impl Default for Pushbox {
+    fn default() -> Self {
+        Self {
+            half_width: 0.4,
+            height: 1.8,
+        }
+    }
+}
+
+impl Pushbox {
+    /// How far the other fighter has to be pushed away from this one, with this one pushed
+    /// back just as far. `towards` is used when both stand on the same spot.
+    pub fn push(
+        &self,
+        position: Vec3,
+        other: &Pushbox,
+        other_position: Vec3,
+        towards: Vec3,
+    ) -> Option<Vec3> {
+        // Boxes that don't overlap vertically let airborne fighters cross over
+        if other_position.y >= position.y + self.height
+            || position.y >= other_position.y + other.height
+        {
+            return None;
+        }
+
+        let mut between = other_position - position;
+        between.y = 0.;
+
+        let overlap = self.half_width + other.half_width - between.length();
+        if overlap <= 0. {
+            return None;
+        }
+
+        let direction = between.try_normalize().unwrap_or(towards);
+        Some(direction * overlap / 2.)
+    }
+}
+
 #[derive(Default, Debug, Serialize, Deserialize, Clone, FromReflect, Reflect, Component)]
 #[reflect(Component)]
 pub struct HurtboxData {
@@ -328,6 +683,13 @@ pub struct HurtboxData {
     priority: u8,
     #[serde(default)]
     pub id: Option<usize>,
+    #[serde(default)]
+    pub global_id: Option<u32>,
+    // Hurtboxes from states follow this bone, or the fighter without one
+    #[serde(default)]
+    pub bone: Option<String>,
+    #[serde(default)]
+    pub bone_entity: Option<Entity>,
     pub radius: f32,
     #[serde(alias = "halfHeight")]
     pub half_height: f32,
@@ -341,6 +703,12 @@ pub struct HurtboxData {
     pub end_frame: Option<u16>,
 }
 
+impl HurtboxData {
+    pub fn set_global_id(&mut self, global_id: u32) {
+        self.global_id = Some(global_id);
+    }
+}
+
 impl HBox for HurtboxData {
     fn get_priority(&self) -> u8 {
         self.priority
@@ -389,6 +757,17 @@ pub struct ProjectileData {
     pub life_frames: u16,
     #[serde(default)]
     pub damage: u16,
+    #[serde(alias = "onHit", default)]
+    pub on_hit: OnHit,
+    #[serde(default)]
+    pub blockstun: Frame,
+    #[serde(alias = "hitLevel", default)]
+    pub hit_level: HitLevel,
+    #[serde(default)]
+    pub priority: u8,
+    // Frames after hitting that the projectile can hit again
+    #[serde(default)]
+    pub rehit: Option<Frame>,
     #[serde(default = "ProjectileData::max_default")]
     pub max: usize,
 }
@@ -522,7 +901,7 @@ impl FromReflect for DynValue {
     }
 }
 
-#[derive(Component, Reflect)]
+#[derive(Debug, Component, Reflect)]
 #[reflect(Component)]
 pub struct CurrentState(pub Entity);
 
@@ -532,20 +911,36 @@ impl Default for CurrentState {
     }
 }
 
-#[derive(Default, Reflect, Component)]
+#[derive(Default, Debug, Reflect, Component)]
 #[component(storage = "SparseSet")]
 pub struct Active(pub HashSet<Entity>);
 // Ignored Entities
 
-#[derive(Default, Component, Reflect)]
+#[derive(Default, Debug, Component, Reflect)]
 #[reflect(Component)]
 pub struct StateFrame(pub u16);
 
-#[derive(Default, Component, Reflect)]
+/// Fighters a hitbox or projectile already hit, and the frame of its owner they were hit on
+#[derive(Default, Debug, Component, Reflect, Clone)]
 #[reflect(Component)]
-pub struct Exclude(pub HashSet<Entity>);
+pub struct Exclude(pub HashMap<Entity, Frame>);
+
+impl Exclude {
+    /// Whether the recipient can't be hit yet. Without a rehit, it's never hit again.
+    pub fn excludes(&self, recipient: Entity, frame: Frame, rehit: Option<Frame>) -> bool {
+        match (self.0.get(&recipient), rehit) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(hit_frame), Some(rehit)) => frame < hit_frame.saturating_add(rehit),
+        }
+    }
 
-#[derive(Component, PartialEq, Reflect)]
+    pub fn insert(&mut self, recipient: Entity, frame: Frame) {
+        self.0.insert(recipient, frame);
+    }
+}
+
+#[derive(Debug, Component, PartialEq, Reflect)]
 pub struct Owner(pub Entity);
 
 impl Owner {
@@ -596,10 +991,90 @@ pub struct Facing(pub Direction);
 #[reflect(Component)]
 pub struct Health(pub u16);
 
+#[derive(Serialize, Deserialize, Default, Debug, Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Meter(pub u16);
+
+impl Meter {
+    pub fn gain(&mut self, amount: u16, max: u16) {
+        self.0 = self.0.saturating_add(amount).min(max);
+    }
+}
+
 #[derive(Serialize, Deserialize, Default, Debug, Component, Reflect, Clone)]
 #[reflect(Component)]
 pub struct GroundedHitstun(pub Frame);
 
+#[derive(Serialize, Deserialize, Default, Debug, Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Blockstun(pub Frame);
+
+/// Hits the fighter has taken since it could last act, and the damage they dealt
+#[derive(Serialize, Deserialize, Debug, Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Combo {
+    pub hits: u16,
+    pub damage: u16,
+    // Scaling from the hit that started the combo
+    pub starter: f32,
+}
+
+impl Default for Combo {
+    fn default() -> Self {
+        Self {
+            hits: 0,
+            damage: 0,
+            starter: 1.,
+        }
+    }
+}
+
+impl Combo {
+    /// The damage of the next hit of the combo. The first hit isn't scaled.
+    pub fn scale(&self, damage: u16, scaling: &ComboScaling) -> u16 {
+        if self.hits == 0 {
+            return damage;
+        }
+
+        let scale = (self.starter * (1. - scaling.per_hit * (self.hits - 1) as f32)).max(scaling.minimum);
+        (damage as f32 * scale).round() as u16
+    }
+}
+
+/// The last counter hit the fighter landed, and how many frames are left to show it for
+#[derive(Serialize, Deserialize, Default, Debug, Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct LastCounter {
+    pub kind: CounterKind,
+    pub frames: Frame,
+}
+
+/// Juggle points the fighter has taken since it last landed
+#[derive(Serialize, Deserialize, Default, Debug, Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Juggle(pub u8);
+
+/// Frames left of the freeze after a hit. The fighter's frame, movement and projectiles
+/// don't advance while it's active, but inputs are still buffered.
+#[derive(Serialize, Deserialize, Default, Debug, Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Hitstop(pub Frame);
+
+impl Hitstop {
+    pub fn is_active(&self) -> bool {
+        self.0 > 0
+    }
+}
+
+/// On a fighter that's been thrown, until it techs or the throw lands. Only inserted by a grab,
+/// which always sets the thrower. It's optional so the component can be defaulted for rollback.
+#[derive(Default, Debug, Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Grabbed {
+    pub by: Option<Entity>,
+    pub throw: ThrowboxData,
+}
+
 #[derive(Serialize, Deserialize, Default, Debug, Component, Reflect, Clone)]
 #[reflect(Component)]
 pub struct PlayerAxis {
@@ -628,6 +1103,11 @@ impl FrameWindow {
         self.end.expect("End frame doesn't exist in FrameWindow")
     }
 
+    // Whether the frame is inside the window, where a missing start or end is unbounded
+    pub fn contains(&self, frame: Frame) -> bool {
+        self.start.map_or(true, |start| frame >= start) && self.end.map_or(true, |end| frame <= end)
+    }
+
     pub fn try_get_start_frame(&self) -> Result<Frame, ()> {
         self.start.ok_or(())
     }
@@ -701,3 +1181,129 @@ impl From<FrameWindowHelper> for FrameWindow {
 
 #[derive(Serialize, Deserialize, Default, Debug, Component, Reflect, Clone)]
 pub struct FighterPosition;
+
+#[test]
+fn guard_height_decides_what_is_blocked() {
+    let back = StateInput {
+        x: DirectionalInput::Negative,
+        ..Default::default()
+    };
+    let down_back = StateInput {
+        x: DirectionalInput::Negative,
+        y: DirectionalInput::Negative,
+        ..Default::default()
+    };
+
+    assert!(HitLevel::High.blocked_by(&back));
+    assert!(!HitLevel::High.blocked_by(&down_back));
+    assert!(HitLevel::Middle.blocked_by(&back));
+    assert!(HitLevel::Middle.blocked_by(&down_back));
+    assert!(!HitLevel::Low.blocked_by(&back));
+    assert!(HitLevel::Low.blocked_by(&down_back));
+    assert!(!HitLevel::Middle.blocked_by(&StateInput::default()));
+}
+
+#[test]
+fn pushboxes_push_apart_unless_crossing_over() {
+    let pushbox = Pushbox::default();
+
+    let push = pushbox
+        .push(Vec3::ZERO, &pushbox, Vec3::new(0.5, 0., 0.), Vec3::X)
+        .unwrap();
+    assert!((push - Vec3::new(0.15, 0., 0.)).length() < 0.0001);
+
+    assert!(pushbox.push(Vec3::ZERO, &pushbox, Vec3::new(1., 0., 0.), Vec3::X).is_none());
+    assert!(pushbox.push(Vec3::ZERO, &pushbox, Vec3::new(0.2, 2., 0.), Vec3::X).is_none());
+
+    // On the same spot, they're pushed apart along the given direction
+    assert_eq!(
+        pushbox.push(Vec3::ZERO, &pushbox, Vec3::ZERO, Vec3::X),
+        Some(Vec3::new(0.4, 0., 0.))
+    );
+}
+
+#[test]
+fn invulnerability_and_armor_follow_their_windows() {
+    let state = State {
+        invulnerability: vec![
+            Invulnerability {
+                window: [1, 5].into(),
+                to: InvulnerableTo::All,
+            },
+            Invulnerability {
+                window: [6, 10].into(),
+                to: InvulnerableTo::Throws,
+            },
+        ],
+        armor: Some(Armor {
+            window: [8, 12].into(),
+            hits: 1,
+        }),
+        ..Default::default()
+    };
+
+    assert!(state.strike_invulnerable(3) && state.throw_invulnerable(3));
+    assert!(!state.strike_invulnerable(7) && state.throw_invulnerable(7));
+    assert!(!state.strike_invulnerable(11) && !state.throw_invulnerable(11));
+    assert_eq!(state.armor_hits(7), 0);
+    assert_eq!(state.armor_hits(12), 1);
+}
+
+#[test]
+fn rehit_lets_a_hitbox_hit_again() {
+    let fighter = Entity::from_raw(0);
+    let mut exclude = Exclude::default();
+    assert!(!exclude.excludes(fighter, 5, None));
+
+    exclude.insert(fighter, 5);
+    assert!(exclude.excludes(fighter, 20, None));
+    assert!(exclude.excludes(fighter, 7, Some(3)));
+    assert!(!exclude.excludes(fighter, 8, Some(3)));
+}
+
+#[test]
+fn combo_damage_scales_down_to_the_minimum() {
+    let scaling = ComboScaling {
+        per_hit: 0.1,
+        minimum: 0.5,
+    };
+    let mut combo = Combo::default();
+    assert_eq!(combo.scale(100, &scaling), 100);
+
+    combo.hits = 1;
+    assert_eq!(combo.scale(100, &scaling), 100);
+    combo.hits = 3;
+    assert_eq!(combo.scale(100, &scaling), 80);
+    combo.hits = 20;
+    assert_eq!(combo.scale(100, &scaling), 50);
+
+    combo.hits = 2;
+    combo.starter = 0.8;
+    assert_eq!(combo.scale(100, &scaling), 72);
+}
+
+#[test]
+fn phases_follow_the_active_frames() {
+    let state = State {
+        active_frames: Some([5, 8].into()),
+        ..Default::default()
+    };
+
+    assert_eq!(state.phase(4), Some(Phase::Startup));
+    assert_eq!(state.phase(5), Some(Phase::Active));
+    assert_eq!(state.phase(9), Some(Phase::Recovery));
+    assert_eq!(State::default().phase(1), None);
+}
+
+#[test]
+fn meter_gain_stops_at_the_max() {
+    let mut meter = Meter(90);
+    meter.gain(5, 100);
+    assert_eq!(meter.0, 95);
+    meter.gain(20, 100);
+    assert_eq!(meter.0, 100);
+
+    let mut meter = Meter(u16::MAX - 1);
+    meter.gain(10, u16::MAX);
+    assert_eq!(meter.0, u16::MAX);
+}
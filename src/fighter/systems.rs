@@ -1,19 +1,21 @@
 #![allow(clippy::type_complexity)]
 
+use std::cmp::Reverse;
+
 use super::{
     animation::components::PositionEntity,
-    data::{Collider, CollisionData, FighterData, HitEvent},
+    conditions::ConditionContext,
+    data::{BlockEvent, Collider, CollisionData, CounterKind, FighterData, HitEvent, HitReactions},
     event::TransitionEvent,
-    hit::components::{AirborneHitstun, OnHit},
+    hit::components::{AirborneHitstun, KnockedDown, Knockdown, OnHit, ThrowboxData, CROUCH_HITSTUN_BONUS},
     modifiers::{
         AdjustFacing, CreateObject, InputMet, InputWindowCheck, Object, OnExitSetPos,
         OnExitZeroVelo, VectorType, Velo,
     },
     state::{
-        Active, ActiveHitboxes, BoneMap, Conditions, CurrentState, Direction, Exclude, Facing,
-        GroundedHitstun, HBox, Health, HurtboxData, Hurtboxes, Owner, PlayerAxis, ProjectileData,
-        ProjectileReference, State, StateFrame, StateHeight, StateMap, Velocity, AIR_HITSTUN,
-        GRND_HITSTUN_KB,
+        AbsorbedHits, Active, ActiveHitboxes, ActiveHurtboxes, Blockstun, BoneMap, Conditions, Contact, Grabbed, Hitstop, Combo, ContactKind, CurrentState, Juggle, LastCounter, Meter, FORWARD_WALK, Direction, Exclude, Facing, Frame,
+        GroundedHitstun, HBox, Health, HurtboxData, Hurtboxes, Owner, Phase, PlayerAxis, ProjectileData,
+        ProjectileReference, State, StateFrame, StateHeight, StateMap, Velocity,
     },
     Fighter,
 };
@@ -27,6 +29,7 @@ use bevy::{
         Without,
     },
     reflect::{FromReflect, Reflect, Struct},
+    text::Text,
     ui::{Style, Val},
     utils::{default, hashbrown::HashSet, HashMap},
 };
@@ -44,9 +47,10 @@ use parry3d::{
 use bevy::input::Input;
 
 use crate::{
-    battle::{HitboxMaterial, Lifebar, MatchCamera, MatchCameraRoot, PlayerEntities},
+    battle::{ComboCounter, CounterPopup, HitboxMaterial, HurtboxMaterial, Lifebar, MeterBar, MatchCamera, MatchCameraRoot, PlayerEntities},
     fighter::hit::components::HitboxData,
     game::{Paused, RoundState},
+    input::{CommandInput, DirectionalInput},
     util::{scripting::{PlayerEntityArg}, Buffer},
     GGRSConfig, HitboxMap, Player, FPS,
 };
@@ -80,10 +84,7 @@ pub fn buffer_insert_system(
     inputs: Res<PlayerInputs<GGRSConfig>>,
 ) {
     for (mut buffer, player) in query.iter_mut() {
-        if player.0 != 1 {
-            return;
-        }
-        buffer.0.insert(inputs[0].0 .0)
+        buffer.0.insert(inputs[player.0 as usize - 1].0 .0)
     }
 }
 
@@ -98,12 +99,17 @@ pub fn movement_system(
         &FighterData,
         &Facing,
         &PlayerAxis,
+        &Hitstop,
     )>,
 
     facing_query: Query<&AdjustFacing>,
 ) {
-    for (map, current, mut tf, mut velocity, frame, data, facing, axis) in fighter_query.iter_mut()
+    for (map, current, mut tf, mut velocity, frame, data, facing, axis, hitstop) in
+        fighter_query.iter_mut()
     {
+        if hitstop.is_active() {
+            continue;
+        }
 
         // if let Ok(velo) = query.get(current.0) {
         //     if frame.0 == 1 {
@@ -160,14 +166,80 @@ pub fn movement_system(
     }
 }
 
+/// Pushes overlapping fighters apart, each by half of the overlap
+pub fn pushbox_system(
+    players: Res<PlayerEntities>,
+    mut fighter_query: Query<
+        (&CurrentState, &FighterData, &PlayerAxis, &mut Transform),
+        With<Fighter>,
+    >,
+    state_query: Query<&State>,
+) {
+    let Ok([(current1, data1, axis1, mut tf1), (current2, data2, _, mut tf2)]) =
+        fighter_query.get_many_mut(players.as_ref().into())
+    else {
+        return;
+    };
+
+    let pushbox1 = state_query
+        .get(current1.0)
+        .ok()
+        .and_then(|state| state.pushbox)
+        .unwrap_or(data1.pushbox);
+    let pushbox2 = state_query
+        .get(current2.0)
+        .ok()
+        .and_then(|state| state.pushbox)
+        .unwrap_or(data2.pushbox);
+
+    if let Some(push) = pushbox1.push(tf1.translation, &pushbox2, tf2.translation, axis1.x) {
+        tf1.translation -= push;
+        tf2.translation += push;
+    }
+}
+
 pub fn increment_frame_system(
-    mut query: Query<&mut StateFrame, Or<(With<Fighter>, With<Active>)>>,
+    mut query: Query<(Entity, &mut StateFrame, Option<&Owner>), Or<(With<Fighter>, With<Active>)>>,
+    hitstop_query: Query<&Hitstop>,
 ) {
-    for mut frame in query.iter_mut() {
+    for (entity, mut frame, owner) in query.iter_mut() {
+        // Objects freeze along with the fighter that owns them
+        let fighter = owner.map_or(entity, |owner| owner.0);
+        if hitstop_query.get(fighter).map_or(false, |hitstop| hitstop.is_active()) {
+            continue;
+        }
+
         frame.0 = frame.0.checked_add(1).unwrap_or(1);
     }
 }
 
+/// Fills the meter of fighters walking forward
+pub fn meter_system(
+    mut query: Query<(&CurrentState, &StateMap, &FighterData, &Hitstop, &mut Meter), With<Fighter>>,
+) {
+    for (current, map, data, hitstop, mut meter) in query.iter_mut() {
+        if !hitstop.is_active() && map.get(&FORWARD_WALK) == Some(&current.0) {
+            meter.gain(data.meter_gain.walk_forward, data.meter_max);
+        }
+    }
+}
+
+pub fn hitstop_system(mut query: Query<&mut Hitstop, With<Fighter>>) {
+    for mut hitstop in query.iter_mut() {
+        hitstop.0 = hitstop.0.saturating_sub(1);
+    }
+}
+
+// How long the counter hit popup stays up, in frames
+const COUNTER_POPUP_FRAMES: Frame = 60;
+
+pub fn last_counter_system(mut query: Query<&mut LastCounter, With<Fighter>>) {
+    for mut last_counter in query.iter_mut() {
+        last_counter.frames = last_counter.frames.saturating_sub(1);
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn hitstun_system(
     mut commands: Commands,
     mut query: Query<
@@ -179,30 +251,119 @@ pub fn hitstun_system(
             &mut Velocity,
             Option<&GroundedHitstun>,
             Option<&AirborneHitstun>,
+            Option<&Blockstun>,
+            Option<&KnockedDown>,
             &mut Transform,
+            &FighterData,
+            &InputBuffer,
+            &Facing,
+            &mut Juggle,
+            &mut Combo,
         ),
         With<Fighter>,
     >,
 ) {
-    for (fighter, map, mut current, mut frame, mut velo, hitstun, airborne, mut tf) in
-        query.iter_mut()
+    for (
+        fighter,
+        map,
+        mut current,
+        mut frame,
+        mut velo,
+        hitstun,
+        airborne,
+        blockstun,
+        knocked_down,
+        mut tf,
+        data,
+        buffer,
+        facing,
+        mut juggle,
+        mut combo,
+    ) in query.iter_mut()
     {
+        let reactions = &data.hit_reactions;
+
+        if let Some(blockstun) = blockstun {
+            if frame.0 > blockstun.0 {
+                // Crouching blockstun goes back to crouching
+                let crouch_block = map.get(&reactions.crouch_block);
+                let id = if crouch_block == Some(&current.0) { 20 } else { 0 };
+
+                frame.0 = 1;
+                current.0 = *map.get(&id).expect("State with given ID doesn't exist");
+                velo.0 = Vec3::ZERO;
+                commands.entity(fighter).remove::<Blockstun>();
+            }
+        }
+
         if let Some(hitstun) = hitstun {
             if frame.0 > hitstun.0 {
                 frame.0 = 1;
                 current.0 = *map.get(&0).expect("State with ID 0 doesn't exist");
                 velo.0 = Vec3::ZERO;
+                *combo = Combo::default();
                 commands.entity(fighter).remove::<GroundedHitstun>();
             }
         }
 
-        if airborne.is_some() {
+        if let Some(airborne) = airborne {
             if tf.translation.y <= 0. && frame.0 != 1 {
                 frame.0 = 1;
                 tf.translation.y = 0.;
-                current.0 = *map.get(&0).expect("State with ID 0 doesn't exist");
+
+                if let Some(bounce) = airborne.ground_bounce {
+                    // Bounces once, then lands as usual. Velocity is along the axis to the opponent.
+                    current.0 = *map
+                        .get(&reactions.air)
+                        .expect("State with given ID doesn't exist");
+                    velo.0 = Vec3::new(-bounce.x, bounce.y, bounce.z);
+                    commands.entity(fighter).insert(AirborneHitstun {
+                        ground_bounce: None,
+                        ..airborne.clone()
+                    });
+                } else if let Some(knockdown) = airborne.knockdown {
+                    let id = match knockdown {
+                        Knockdown::Soft(_) => reactions.soft_knockdown,
+                        Knockdown::Hard(_) => reactions.hard_knockdown,
+                    };
+
+                    current.0 = *map.get(&id).expect("State with given ID doesn't exist");
+                    velo.0 = Vec3::ZERO;
+                    juggle.0 = 0;
+                    commands
+                        .entity(fighter)
+                        .remove::<AirborneHitstun>()
+                        .insert(KnockedDown(knockdown));
+                } else {
+                    current.0 = *map.get(&0).expect("State with ID 0 doesn't exist");
+                    velo.0 = Vec3::ZERO;
+                    juggle.0 = 0;
+                    *combo = Combo::default();
+                    commands.entity(fighter).remove::<AirborneHitstun>();
+                }
+            }
+        }
+
+        if let Some(knocked_down) = knocked_down {
+            // The wakeup window opens on landing, so presses from the juggle before it don't count
+            let window = frame.0 as usize;
+            let wakeup = match knocked_down.0 {
+                Knockdown::Soft(_) if data.wakeup.back_rise.compare_recent(&buffer.0, facing.0, window) => {
+                    Some(reactions.back_rise)
+                }
+                Knockdown::Soft(_) if data.wakeup.quick_rise.compare_recent(&buffer.0, facing.0, window) => {
+                    Some(reactions.quick_rise)
+                }
+                _ if frame.0 > knocked_down.0.duration() => Some(0),
+                _ => None,
+            };
+
+            if let Some(id) = wakeup {
+                frame.0 = 1;
+                current.0 = *map.get(&id).expect("State with given ID doesn't exist");
                 velo.0 = Vec3::ZERO;
-                commands.entity(fighter).remove::<AirborneHitstun>();
+                *combo = Combo::default();
+                commands.entity(fighter).remove::<KnockedDown>();
             }
         }
     }
@@ -215,12 +376,15 @@ pub fn process_input_system(
         (
             Entity,
             &CurrentState,
-            &StateMap,
             &InputBuffer,
             &StateFrame,
-            &Player,
             &Facing,
             &Transform,
+            &Velocity,
+            &Health,
+            &Meter,
+            &PlayerAxis,
+            Option<&Contact>,
         ),
         (With<Fighter>, With<Player>),
     >,
@@ -230,217 +394,120 @@ pub fn process_input_system(
     mut lua_writer: PriorityEventWriter<LuaEvent<PlayerEntityArg>>,
 
     input_met_mod_query: Query<&InputMet>,
+    hitstop_query: Query<&Hitstop>,
 ) {
-    'fighter: for (fighter, current, map, buffer, frame, player, facing, tf) in query.iter() {
-        //let state: &Entity = map.get(&current.0).expect("State doesn't exist");
-
-        // if current.0 == 250 {
-        //     let q: StateInput = buffer.0.get(0).unwrap().into();
-        //     println!("250 Input: {:?}", q);
-        // }
-
-        let current_id = state_query
-            .get(current.0)
-            .expect("Entity doesn't contain State component")
-            .1
-            .id;
-
-        if let Ok((_, s)) = state_query.get(current.0) {
-            'transitions: for (to_state_entity, to_state) in state_query.iter_many(&s.transitions) {
-                if let Some(all) = &to_state.triggers.0 {
-                    let mut meets_conditions = true;
-                    'all: for condition in all.iter() {
-                        match condition {
-                            Conditions::In(n) => {
-                                if !n.contains(&current_id) {
-                                    meets_conditions = false;
-                                    break 'all;
-                                }
-                            }
-                            Conditions::NotIn(n) => {
-                                if n == &current_id {
-                                    meets_conditions = false;
-                                    break 'all;
-                                }
-                            }
-                            Conditions::Command(command) => {
-                                if !command.compare(&buffer.0, facing.0) {
-                                    meets_conditions = false;
-                                    break 'all;
-                                }
-                            }
-                            Conditions::EndDuration => {
-                                if frame.0 <= s.duration.expect("State doesn't have duration") {
-                                    meets_conditions = false;
-                                    break 'all;
-                                }
-                            }
-                            Conditions::Frame(window) => {
-                                if let Ok(start) = window.try_get_start_frame() {
-                                    if frame.0 < start {
-                                        meets_conditions = false;
-                                        break 'all;
-                                    }
-                                }
-
-                                if let Ok(end) = window.try_get_end_frame() {
-                                    if frame.0 > end {
-                                        meets_conditions = false;
-                                        break 'all;
-                                    }
-                                }
-                            }
-                            Conditions::ReachGround => {
-                                if tf.translation.y > 0. {
-                                    meets_conditions = false;
-                                    break 'all;
-                                }
-                            }
-                            Conditions::InputWindowCon(enact_frame) => {
-                                panic!()
-                            }
-                            Conditions::True => {},
-                            // Conditions::OnHit(id, range) => {
-                            //     if let Some(id) = id {
-                            //         todo!()
-                            //     }
-                            // },
-                        }
-                    }
-
-                    if !meets_conditions {
-                        continue 'transitions;
-                    }
-                }
-
-                let mut others = true;
-
-                'set: for con_set in to_state.triggers.1.iter() {
-                    let mut met = true;
-                    others = false;
-                    'conditions: for conditions in con_set.iter() {
-                        match conditions {
-                            Conditions::In(n) => {
-                                if !n.contains(&current_id) {
-                                    met = false;
-                                    break 'conditions;
-                                }
-                            }
-                            Conditions::NotIn(n) => {
-                                if n == &current_id {
-                                    met = false;
-                                    break 'conditions;
-                                }
-                            }
-                            Conditions::Command(command) => {
-                                if !command.compare(&buffer.0, facing.0) {
-                                    met = false;
-                                    break 'conditions;
-                                }
-                            }
+    let current_ids: Vec<(Entity, u16)> = query
+        .iter()
+        .filter_map(|(fighter, current, ..)| {
+            state_query
+                .get(current.0)
+                .ok()
+                .map(|(_, state)| (fighter, state.id))
+        })
+        .collect();
 
-                            Conditions::EndDuration => {
-                                if frame.0 <= s.duration.expect("State doesn't have duration") {
-                                    met = false;
-                                    break 'conditions;
-                                }
-                            }
-                            Conditions::Frame(window) => {
-                                if let Ok(start) = window.try_get_start_frame() {
-                                    if frame.0 < start {
-                                        met = false;
-                                        break 'conditions;
-                                    }
-                                }
+    for (fighter, current, buffer, frame, facing, tf, velo, health, meter, axis, contact) in
+        query.iter()
+    {
+        // Inputs stay in the buffer, so a cancel made during hitstop comes out once it's over
+        if hitstop_query.get(fighter).map_or(false, |hitstop| hitstop.is_active()) {
+            continue;
+        }
 
-                                if let Ok(end) = window.try_get_end_frame() {
-                                    if frame.0 > end {
-                                        met = false;
-                                        break 'conditions;
-                                    }
-                                }
-                            }
-                            Conditions::ReachGround => {
-                                if tf.translation.y > 0. {
-                                    met = false;
-                                    break 'conditions;
-                                }
-                            }
-                            Conditions::InputWindowCon(enact_frame) => {
-                                panic!()
-                            }
-                            Conditions::True => {},
-                        }
-                    }
-                    if met {
-                        trans_writer.send(TransitionEvent::new(fighter, to_state.id));
-
-                        lua_writer.send(
-                            LuaEvent {
-                                hook_name: "exit".to_owned(),
-                                args: PlayerEntityArg::new(fighter),
-                                recipients: Recipients::Entity(current.0),
-                            },
-                            0,
-                        );
-
-                        lua_writer.send(
-                            LuaEvent { 
-                                hook_name: "enter".to_owned(), 
-                                args: PlayerEntityArg::new(fighter), 
-                                recipients: Recipients::Entity(to_state_entity)
-                            },
-                            1
-                        );
-
-                        // lua_writer.send(
-                        //     LuaEvent { 
-                        //         hook_name: "process".to_owned(), 
-                        //         args: PlayerEntityArg(fighter), 
-                        //         recipients: Recipients::Entity(to_state_entity)
-                        //     },
-                        //     2
-                        // );
-
-                        break 'transitions;
-                    }
-                }
+        let Ok((_, s)) = state_query.get(current.0) else {
+            continue;
+        };
+
+        let context = ConditionContext {
+            current_id: s.id,
+            state: s,
+            frame: frame.0,
+            buffer: &buffer.0,
+            facing: facing.0,
+            transform: tf,
+            velocity: velo,
+            health: health.0,
+            meter: meter.0,
+            axis,
+            opponent_state: current_ids
+                .iter()
+                .find(|(other, _)| *other != fighter)
+                .map(|(_, id)| *id),
+            input_met: input_met_mod_query.get(current.0).ok().map(|met| met.0),
+        };
+
+        let contact = contact.map_or(ContactKind::None, |contact| contact.in_state(current.0));
+
+        if let Some((to_state_entity, to_state)) = next_state(&state_query, contact, &context) {
+            trans_writer.send(TransitionEvent::new(fighter, to_state.id));
+
+            lua_writer.send(
+                LuaEvent {
+                    hook_name: "exit".to_owned(),
+                    args: PlayerEntityArg::new(fighter),
+                    recipients: Recipients::Entity(current.0),
+                },
+                0,
+            );
+
+            lua_writer.send(
+                LuaEvent { 
+                    hook_name: "enter".to_owned(), 
+                    args: PlayerEntityArg::new(fighter), 
+                    recipients: Recipients::Entity(to_state_entity)
+                },
+                1
+            );
+
+            // lua_writer.send(
+            //     LuaEvent { 
+            //         hook_name: "process".to_owned(), 
+            //         args: PlayerEntityArg(fighter), 
+            //         recipients: Recipients::Entity(to_state_entity)
+            //     },
+            //     2
+            // );
+        }
+    }
+}
 
-                if others {
-                    trans_writer.send(TransitionEvent::new(fighter, to_state.id));
+/// The state a fighter transitions to from its current one. Open cancel routes are tried
+/// before the state's usual transitions.
+fn next_state<'a>(
+    state_query: &'a Query<(Entity, &State)>,
+    contact: ContactKind,
+    context: &ConditionContext,
+) -> Option<(Entity, &'a State)> {
+    let s = context.state;
+    let whiffed = contact == ContactKind::None
+        && s.last_active_frame().map_or(false, |last| context.frame > last);
+
+    let candidates = s
+        .cancels
+        .iter()
+        .filter(|route| route.is_open(contact, whiffed, context.frame))
+        .flat_map(|route| route.targets.iter())
+        .chain(s.transitions.iter());
+
+    state_query.iter_many(candidates).find(|(_, to_state)| {
+        if to_state.meter_cost > context.meter {
+            return false;
+        }
 
-                    lua_writer.send(
-                        LuaEvent {
-                            hook_name: "exit".to_owned(),
-                            args: PlayerEntityArg::new(fighter),
-                            recipients: Recipients::Entity(current.0),
-                        },
-                        0,
-                    );
-
-                    lua_writer.send(
-                        LuaEvent { 
-                            hook_name: "enter".to_owned(), 
-                            args: PlayerEntityArg::new(fighter), 
-                            recipients: Recipients::Entity(to_state_entity)
-                        },
-                        1
-                    );
-
-                    // lua_writer.send(
-                    //     LuaEvent { 
-                    //         hook_name: "process".to_owned(), 
-                    //         args: PlayerEntityArg(fighter), 
-                    //         recipients: Recipients::Entity(to_state_entity)
-                    //     },
-                    //     2
-                    // );
-                    
-                    break 'transitions;
-                }
+        if let Some(all) = &to_state.triggers.0 {
+            if !Conditions::all_met(all, context) {
+                return false;
             }
         }
-    }
+
+        // Without any trigger sets, triggerAll alone decides the transition
+        to_state.triggers.1.is_empty()
+            || to_state
+                .triggers
+                .1
+                .iter()
+                .any(|con_set| Conditions::all_met(con_set, context))
+    })
 }
 
 #[allow(clippy::type_complexity)]
@@ -463,10 +530,19 @@ pub fn transition_system(
     set_pos_query: Query<&OnExitSetPos>,
     zero_velo_query: Query<&OnExitZeroVelo>,
     mut input_met_query: Query<&mut InputMet>,
+    mut contact_query: Query<&mut Contact>,
+    mut absorbed_query: Query<&mut AbsorbedHits>,
+    mut meter_query: Query<&mut Meter>,
+    hitstop_query: Query<&Hitstop>,
 
     mut transform_set: ParamSet<(Query<&GlobalTransform>, Query<&mut Transform>)>,
 ) {
     for event in trans_reader.iter() {
+        // A fighter can't leave its state while it's frozen
+        if hitstop_query.get(event.fighter).map_or(false, |hitstop| hitstop.is_active()) {
+            continue;
+        }
+
         if let Ok((fighter, mut current, map, mut frame, _buffer, bone_map, mut velo)) =
             fighter_query.get_mut(event.fighter)
         {
@@ -502,17 +578,32 @@ pub fn transition_system(
                 met.0 = false;
             }
 
+            // Contact reset, in case the state transitions into itself
+            if let Ok(mut contact) = contact_query.get_mut(fighter) {
+                *contact = Contact::default();
+            }
+
+            if let Ok(mut absorbed) = absorbed_query.get_mut(fighter) {
+                *absorbed = AbsorbedHits::default();
+            }
+
             current.0 = *map
                 .get(&event.to_id)
                 .expect("State with given ID doesn't exist");
             frame.0 = 1;
+
+            if let (Ok(state), Ok(mut meter)) =
+                (state_query.get(current.0), meter_query.get_mut(fighter))
+            {
+                meter.0 = meter.0.saturating_sub(state.meter_cost);
+            }
         }
     }
 
     trans_reader.clear()
 }
 
-#[derive(Default, Component, Reflect)]
+#[derive(Default, Debug, Component, Reflect)]
 #[reflect(Component)]
 pub struct InputBuffer(pub Buffer);
 
@@ -535,6 +626,7 @@ pub fn hitbox_component_system(
     >,
     state_query: Query<&State>,
     hitbox_query: Query<&HitboxData>,
+    hitstop_query: Query<&Hitstop>,
 
     mut rip: ResMut<RollbackIdProvider>,
     hitbox_material: Res<HitboxMaterial>,
@@ -543,6 +635,11 @@ pub fn hitbox_component_system(
     for (entity, current, map, tf, frame, _buffer, facing, axis, mut active_hits) in
         fighter_query.iter_mut()
     {
+        // The frame doesn't advance during hitstop, so its hitboxes would spawn again
+        if hitstop_query.get(entity).map_or(false, |hitstop| hitstop.is_active()) {
+            continue;
+        }
+
         //let state = map.get(&current.0).expect("State doesn't exist.");
 
         if let Ok(s) = state_query.get(current.0) {
@@ -602,7 +699,7 @@ pub fn hitbox_component_system(
                                 Rollback::new(rip.next_id()),
                                 hit_collider.1.clone(),
                                 Owner(entity),
-                                Exclude(HashSet::new()),
+                                Exclude::default(),
                             ))
                             .set_parent(hitbox.bone_entity.expect("Bone entity doesn't exist"))
                             .id();
@@ -629,72 +726,106 @@ pub fn hitbox_removal_system(
     }
 }
 
-#[allow(clippy::type_complexity)]
+/// Spawns the hurtboxes of the fighter's state on their start frame, following their bone
 pub fn hurtbox_component_system(
     mut commands: Commands,
-    mut fighter_query: Query<
-        (
-            &CurrentState,
-            &StateMap,
-            &Transform,
-            &StateFrame,
-            &InputBuffer,
-            &Facing,
-            &PlayerAxis,
-        ),
-        With<Fighter>,
-    >,
+    mut fighter_query: Query<(Entity, &CurrentState, &StateFrame, &mut ActiveHurtboxes), With<Fighter>>,
+    hurtbox_query: Query<(Entity, &Owner), With<HurtboxData>>,
     state_query: Query<&State>,
-    hurtbox_query: Query<&HurtboxData>,
+    hitstop_query: Query<&Hitstop>,
+
+    mut rip: ResMut<RollbackIdProvider>,
+    hurtbox_material: Res<HurtboxMaterial>,
+    hitbox_resource: Res<HitboxMap>,
 ) {
-    // for (current, map, tf, frame, _buffer, facing, axis) in fighter_query.iter_mut() {
-    //     let state = map.get(&current.0).expect("State doesn't exist.");
-
-    //     if let Ok(s) = state_query.get(*state) {
-    //         if let Some(hurtboxes) = &s.hurtboxes {
-    //             if frame.0 == 1 {
-    //                 if let Some(zero_set) = hurtboxes.get(&0) {
-    //                     for h in zero_set {
-    //                         let hurtbox =
-    //                             hurtbox_query.get(*h).expect("Hurtbox entity does not exist");
-    //                         let offset = hurtbox.offset;
-
-    //                         let mut transform = Transform::from_translation(tf.translation);
-    //                         transform.rotate_x(hurtbox.rotation.0);
-    //                         transform.rotate_z(hurtbox.rotation.1);
-    //                         transform.rotate(tf.rotation);
-    //                         transform.translation.y = 0.;
-    //                         transform.translation += offset.x * axis.x;
-    //                         transform.translation += offset.z * axis.z;
-    //                         transform.translation.y += offset.y;
-
-    //                         commands
-    //                             .entity(*h)
-    //                             .insert(Active(HashSet::new()))
-    //                             .insert_bundle(SpatialBundle {
-    //                                 transform,
-    //                                 ..default()
-    //                             });
-    //                     }
-    //                 }
-    //             }
+    for (entity, current, frame, mut active_hurts) in fighter_query.iter_mut() {
+        // The frame doesn't advance during hitstop, so its hurtboxes would spawn again
+        if hitstop_query.get(entity).map_or(false, |hitstop| hitstop.is_active()) {
+            continue;
+        }
+
+        // A state that starts over, even by transitioning into itself, drops the last hurtboxes
+        if frame.0 == 1 {
+            for (hurtbox, _) in hurtbox_query.iter().filter(|(_, owner)| owner.0 == entity) {
+                commands.entity(hurtbox).despawn();
+            }
+            active_hurts.list.clear();
+            active_hurts.state = None;
+        }
+
+        let Some(set) = state_query
+            .get(current.0)
+            .ok()
+            .and_then(|state| state.hurtboxes.as_ref())
+            .and_then(|hurtboxes| hurtboxes.get(&frame.0))
+        else {
+            continue;
+        };
+
+        for hurtbox in set {
+            let global_id = hurtbox.global_id.expect("GlobalID doesn't exist");
+            let hurt_collider = hitbox_resource
+                .0
+                .get(&global_id)
+                .expect("No Hurtbox found for given GlobalID");
+
+            commands
+                .spawn((
+                    PbrBundle {
+                        transform: Transform {
+                            translation: hurtbox.offset,
+                            rotation: Quat::from_euler(
+                                EulerRot::default(),
+                                0.,
+                                hurtbox.rotation.0,
+                                hurtbox.rotation.1,
+                            ),
+                            ..default()
+                        },
+                        mesh: hurt_collider.0.clone(),
+                        material: hurtbox_material.0.clone(),
+                        ..default()
+                    },
+                    hurtbox.clone(),
+                    Rollback::new(rip.next_id()),
+                    hurt_collider.1.clone(),
+                    Owner(entity),
+                ))
+                .set_parent(hurtbox.bone_entity.unwrap_or(entity));
+
+            active_hurts.state = Some(current.0);
+            active_hurts.list.push(global_id);
+        }
+    }
 }
 
+/// Despawns state hurtboxes after their end frame, or once the fighter leaves their state
 pub fn hurtbox_removal_system(
     mut commands: Commands,
-    mut query: Query<(Entity, &HurtboxData, &Owner, &mut Visibility), With<Active>>,
-    fighter_query: Query<&StateFrame, With<Fighter>>,
+    mut fighter_query: Query<(Entity, &CurrentState, &StateFrame, &mut ActiveHurtboxes), With<Fighter>>,
+    hurtbox_query: Query<(Entity, &HurtboxData, &Owner)>,
 ) {
-    // for (entity, data, owner, mut visible) in query.iter_mut() {
-    //     let frame = fighter_query.get(owner.0).expect("Owner doesn't exist");
+    for (fighter, current, frame, mut active_hurts) in fighter_query.iter_mut() {
+        let left_state = active_hurts.state != Some(current.0);
 
-    //     if let Some(end_frame) = data.end_frame {
-    //         if frame.0 > end_frame {
-    //             visible.is_visible = false;
-    //             commands.entity(entity).remove::<Active>();
-    //         }
-    //     }
-    // }
+        for (hurtbox, data, _) in hurtbox_query.iter().filter(|(.., owner)| owner.0 == fighter) {
+            let expired = left_state || data.end_frame.map_or(false, |end_frame| frame.0 > end_frame);
+
+            if expired {
+                commands.entity(hurtbox).despawn();
+                active_hurts.list.retain(|global_id| Some(*global_id) != data.global_id);
+            }
+        }
+
+        // Hurtboxes spawned this frame aren't in the world yet, but they're from the current state
+        if left_state {
+            active_hurts.list.clear();
+        }
+
+        if active_hurts.list.is_empty() {
+            active_hurts.state = None;
+        }
+    }
 }
 
 // Isn't removed until after stage is over, may be a problem?
@@ -715,9 +846,14 @@ pub fn projectile_system(
         With<Active>,
     >,
     mut fighter_query: Query<(&mut ProjectileReference, &Facing)>,
+    hitstop_query: Query<&Hitstop>,
 ) {
     for (projectile, owner, data, frame, mut visibility, mut tf, mut velo, axis) in query.iter_mut()
     {
+        if hitstop_query.get(owner.0).map_or(false, |hitstop| hitstop.is_active()) {
+            continue;
+        }
+
         if frame.0 == data.life_frames {
             if let Ok((mut proj_ref, _facing)) = fighter_query.get_mut(owner.0) {
                 let ids = proj_ref
@@ -861,6 +997,7 @@ pub fn object_system(
                         commands
                             .entity(id)
                             .insert(axis.clone())
+                            .insert(Exclude::default())
                             .insert(Active(HashSet::new()));
                     }
                 }
@@ -907,94 +1044,340 @@ pub fn hbox_position_system<T: HBox>(
     }
 }
 
-pub fn collision_system(
-    hitbox_query: Query<(Entity, &Owner, &Exclude), With<HitboxData>>,
-    hurtbox_query: Query<(Entity, &Owner, &Name), With<HurtboxData>>,
+/// Highest priority first. Ties go to the hitbox loaded first, then to the projectile with the
+/// lowest rollback id, so every peer picks the same one whatever order the queries return.
+fn candidate_order(hitbox: &HitboxData, projectile: Option<u32>) -> (Reverse<u8>, Option<u32>, Option<u32>) {
+    (Reverse(hitbox.priority), hitbox.global_id, projectile)
+}
 
-    hit_query: Query<(Entity, &HitboxData, &Collider, &GlobalTransform)>,
-    hurt_query: Query<(Entity, &HurtboxData, &Collider, &GlobalTransform)>,
+pub fn collision_system(
+    hitbox_query: Query<(&HitboxData, &Owner, &Exclude, &Collider, &GlobalTransform)>,
+    projectile_query: Query<
+        (
+            Entity,
+            &ProjectileData,
+            &Owner,
+            &Exclude,
+            &StateFrame,
+            &Collider,
+            &GlobalTransform,
+            &Rollback,
+        ),
+        With<Active>,
+    >,
+    hurt_query: Query<(&HurtboxData, &Collider, &GlobalTransform)>,
+    state_hurt_query: Query<(&HurtboxData, &Collider, &GlobalTransform, &Owner)>,
 
-    fighter_query: Query<&Hurtboxes, With<Fighter>>,
+    fighter_query: Query<(&Hurtboxes, &ActiveHurtboxes, &CurrentState, &StateFrame), With<Fighter>>,
+    state_query: Query<&State>,
 
     mut hit_writer: EventWriter<HitEvent>,
 
     players: Res<PlayerEntities>,
 ) {
-    // let mut seen_hitboxes: HashMap<Entity, (Isometry3<f32>, Capsule, HitboxData)> = HashMap::new();
-    // let mut seen_hurtboxes: HashMap<Entity, (Isometry3<f32>, Capsule, HurtboxData)> = HashMap::new();
-    // Entities are attacker, recipient
-    let mut collisions: HashMap<(Entity, Entity), CollisionData> = HashMap::new();
-
-    let mut hitboxes_1: Vec<Entity> = Vec::new();
-    let mut hitboxes_2: Vec<Entity> = Vec::new();
-
-    for (hit_entity, owner, exclude) in hitbox_query.iter() {
-        if owner.0 == players.get(1) && !exclude.0.contains(&players.get(2)) {
-            hitboxes_1.push(hit_entity);
-        } else if owner.0 == players.get(2) && !exclude.0.contains(&players.get(1)) {
-            hitboxes_2.push(hit_entity);
+    let [player1, player2]: [Entity; 2] = players.as_ref().into();
+
+    // Both directions are checked before any hit is applied, so when both fighters
+    // connect on the same frame it's a trade and both hits land, player 1's first
+    let mut collisions: Vec<CollisionData> = Vec::new();
+
+    for (attacker, recipient) in [(player1, player2), (player2, player1)] {
+        let (Ok((hurtboxes, active_hurts, current, frame)), Ok((.., attacker_frame))) =
+            (fighter_query.get(recipient), fighter_query.get(attacker))
+        else {
+            continue;
+        };
+        let Ok(state) = state_query.get(current.0) else {
+            continue;
+        };
+
+        if state.strike_invulnerable(frame.0) {
+            continue;
         }
-    }
-
-    if let Ok([hurtboxes1, hurtboxes2]) = fighter_query.get_many(players.as_ref().into()) {
-        if !hitboxes_1.is_empty() {
-            let mut hurt_grouping: Vec<(Isometry3<f32>, Capsule, HurtboxData)> = Vec::new();
-            let mut hurt_points: Vec<Point<f32>> = Vec::new();
 
-            for (hurt_ent, hurt_data, hurt_shape, hurt_tf) in
-                hurt_query.iter_many(hurtboxes2.list())
-            {
-                let transform: Transform = hurt_tf.compute_transform();
-                let iso: Isometry3<f32> = (transform.translation, transform.rotation).into();
-                let capsule: Capsule = hurt_shape.into();
-                let aabb = Capsule::aabb(&capsule, &iso);
-                hurt_points.push(aabb.mins);
-                hurt_points.push(aabb.maxs);
-                hurt_grouping.push((iso, capsule, hurt_data.clone()));
-            }
+        let model_hurtboxes: &[Entity] = if state.replace_hurtboxes {
+            &[]
+        } else {
+            hurtboxes.list()
+        };
+
+        let mut hurt_grouping: Vec<(Isometry3<f32>, Capsule, HurtboxData)> = Vec::new();
+        let mut hurt_points: Vec<Point<f32>> = Vec::new();
+
+        let state_hurtboxes = state_hurt_query
+            .iter()
+            .filter(|(data, .., owner)| {
+                owner.0 == recipient && data.global_id.map_or(false, |id| active_hurts.list.contains(&id))
+            })
+            .map(|(data, collider, tf, _)| (data, collider, tf));
+
+        for (hurt_data, hurt_shape, hurt_tf) in hurt_query.iter_many(model_hurtboxes).chain(state_hurtboxes) {
+            let transform: Transform = hurt_tf.compute_transform();
+            let iso: Isometry3<f32> = (transform.translation, transform.rotation).into();
+            let capsule: Capsule = hurt_shape.into();
+            let aabb = Capsule::aabb(&capsule, &iso);
+            hurt_points.push(aabb.mins);
+            hurt_points.push(aabb.maxs);
+            hurt_grouping.push((iso, capsule, hurt_data.clone()));
+        }
 
-            let comp_aabb = Aabb::from_points(&hurt_points);
+        if hurt_grouping.is_empty() {
+            continue;
+        }
 
-            'hitbox_loop: for (hit_ent, hitbox1, collider1, tf1) in hit_query.iter_many(&hitboxes_1)
-            {
-                let capsule: Capsule = collider1.into();
-                let transform: Transform = tf1.compute_transform();
-                let iso: Isometry3<f32> = (transform.translation, transform.rotation).into();
-
-                let aabb = capsule.aabb(&iso);
-
-                if comp_aabb.intersects(&aabb) {
-                    for (hurt_iso, hurt_capsule, hurt_data) in &hurt_grouping {
-                        if let Ok(intersect) =
-                            intersection_test(&iso, &capsule, hurt_iso, hurt_capsule)
-                        {
-                            if intersect {
-                                collisions.insert(
-                                    (players.get(1), players.get(2)),
-                                    CollisionData {
-                                        attacker_box: hitbox1.clone(),
-                                        attacker: players.get(1),
-                                        recipient_box: hurt_data.clone(),
-                                        recipient: players.get(2),
-                                    },
-                                );
-                                continue 'hitbox_loop;
-                            }
-                        }
+        let comp_aabb = Aabb::from_points(&hurt_points);
+
+        let counter = match state.phase(frame.0) {
+            Some(Phase::Startup | Phase::Active) => CounterKind::Counter,
+            Some(Phase::Recovery) => CounterKind::Punish,
+            None => CounterKind::None,
+        };
+
+        let hitboxes = hitbox_query
+            .iter()
+            .filter(|(hitbox, owner, exclude, ..)| {
+                owner.0 == attacker && !exclude.excludes(recipient, attacker_frame.0, hitbox.get_rehit())
+            })
+            .map(|(hitbox, _, _, collider, tf)| (hitbox.clone(), None, collider, tf));
+        let projectiles = projectile_query
+            .iter()
+            .filter(|(_, data, owner, exclude, projectile_frame, ..)| {
+                owner.0 == attacker && !exclude.excludes(recipient, projectile_frame.0, data.rehit)
+            })
+            .map(|(projectile, data, _, _, _, collider, tf, rollback)| {
+                (HitboxData::from_projectile(data), Some((projectile, rollback.id())), collider, tf)
+            });
+
+        let mut candidates: Vec<_> = hitboxes.chain(projectiles).collect();
+        candidates.sort_by_key(|(hitbox, projectile, ..)| {
+            candidate_order(hitbox, projectile.map(|(_, rollback_id)| rollback_id))
+        });
+
+        'hitbox_loop: for (hitbox, projectile, collider, tf) in candidates {
+            let capsule: Capsule = collider.into();
+            let transform: Transform = tf.compute_transform();
+            let iso: Isometry3<f32> = (transform.translation, transform.rotation).into();
+
+            let aabb = capsule.aabb(&iso);
+
+            if comp_aabb.intersects(&aabb) {
+                for (hurt_iso, hurt_capsule, hurt_data) in &hurt_grouping {
+                    if let Ok(true) = intersection_test(&iso, &capsule, hurt_iso, hurt_capsule) {
+                        collisions.push(CollisionData {
+                            attacker_box: hitbox,
+                            attacker,
+                            projectile: projectile.map(|(projectile, _)| projectile),
+                            recipient_box: hurt_data.clone(),
+                            recipient,
+                            counter,
+                        });
+                        break 'hitbox_loop;
                     }
                 }
             }
         }
     }
 
-    for (_, collision) in collisions {
+    for collision in collisions {
         hit_writer.send(HitEvent(collision));
     }
 }
 
+/// Lands the throws that weren't teched in time, then starts new ones. The thrower and the
+/// thrown fighter start their states on the same frame, so their frames stay in sync. When
+/// both fighters grab each other on the same frame, the throws clash and both tech.
+pub fn throw_system(
+    mut commands: Commands,
+    players: Res<PlayerEntities>,
+    mut fighter_query: Query<
+        (
+            Entity,
+            &mut CurrentState,
+            &mut StateFrame,
+            &mut Velocity,
+            &StateMap,
+            &Transform,
+            &InputBuffer,
+            &Facing,
+            &FighterData,
+            Option<&Grabbed>,
+            Option<&GroundedHitstun>,
+            Option<&AirborneHitstun>,
+            Option<&Blockstun>,
+        ),
+        With<Fighter>,
+    >,
+    state_query: Query<&State>,
+    mut hit_writer: EventWriter<HitEvent>,
+) {
+    let [player1, player2]: [Entity; 2] = players.as_ref().into();
+
+    // Fighters that go into their throw tech state
+    let mut techs: Vec<Entity> = Vec::new();
+
+    for (defender, current, frame, _, map, _, buffer, facing, data, grabbed, ..) in
+        fighter_query.iter()
+    {
+        let Some(grabbed) = grabbed else {
+            continue;
+        };
+
+        // Anything else that happened to the thrown fighter breaks the throw
+        let Some(thrower) = grabbed.by.filter(|_| map.get(&data.hit_reactions.thrown) == Some(&current.0))
+        else {
+            commands.entity(defender).remove::<Grabbed>();
+            continue;
+        };
+
+        if frame.0 <= grabbed.throw.tech_window {
+            if grabbed.throw.tech.compare(&buffer.0, facing.0) {
+                techs.push(defender);
+                techs.push(thrower);
+                commands.entity(defender).remove::<Grabbed>();
+            }
+        } else {
+            hit_writer.send(HitEvent(CollisionData {
+                attacker_box: HitboxData::from_throw(&grabbed.throw),
+                attacker: thrower,
+                projectile: None,
+                recipient_box: HurtboxData::default(),
+                recipient: defender,
+                counter: CounterKind::None,
+            }));
+            commands.entity(defender).remove::<Grabbed>();
+        }
+    }
+
+    let mut grabs: Vec<(Entity, Entity, ThrowboxData)> = Vec::new();
+
+    for (attacker, defender) in [(player1, player2), (player2, player1)] {
+        let Ok([attacking, defending]) = fighter_query.get_many([attacker, defender]) else {
+            continue;
+        };
+
+        let (_, current, frame, _, _, tf, _, _, _, grabbed, ..) = attacking;
+        let (
+            _,
+            opp_current,
+            opp_frame,
+            _,
+            _,
+            opp_tf,
+            _,
+            _,
+            opp_data,
+            opp_grabbed,
+            opp_hitstun,
+            opp_airborne,
+            opp_blockstun,
+        ) = defending;
+
+        if grabbed.is_some()
+            || opp_grabbed.is_some()
+            || opp_hitstun.is_some()
+            || opp_airborne.is_some()
+            || opp_blockstun.is_some()
+        {
+            continue;
+        }
+
+        let (Ok(state), Ok(opp_state)) = (state_query.get(current.0), state_query.get(opp_current.0))
+        else {
+            continue;
+        };
+
+        if opp_state.throw_invulnerable(opp_frame.0) {
+            continue;
+        }
+
+        // Jump states don't say they're in the air, so the position decides like it does for hits
+        let opp_in_air = matches!(hit_height(opp_state, opp_tf.translation), StateHeight::Air);
+        let mut between = opp_tf.translation - tf.translation;
+        between.y = 0.;
+
+        let throw = state.throwboxes.iter().find(|throw| {
+            throw.window.contains(frame.0)
+                && throw.air == opp_in_air
+                && between.length() <= throw.range + opp_data.throwable_radius
+        });
+
+        if let Some(throw) = throw {
+            grabs.push((attacker, defender, throw.clone()));
+        }
+    }
+
+    if grabs.len() > 1 {
+        techs.extend([player1, player2]);
+        grabs.clear();
+    }
+
+    for (attacker, defender, throw) in grabs {
+        if let Ok((_, mut current, mut frame, mut velo, map, ..)) = fighter_query.get_mut(attacker) {
+            current.0 = *map
+                .get(&throw.state)
+                .expect("State with given ID doesn't exist");
+            frame.0 = 1;
+            velo.0 = Vec3::ZERO;
+        }
+
+        if let Ok((_, mut current, mut frame, mut velo, map, _, _, _, data, ..)) =
+            fighter_query.get_mut(defender)
+        {
+            current.0 = *map
+                .get(&data.hit_reactions.thrown)
+                .expect("State with given ID doesn't exist");
+            frame.0 = 1;
+            velo.0 = Vec3::ZERO;
+        }
+
+        commands.entity(defender).insert(Grabbed {
+            by: Some(attacker),
+            throw,
+        });
+    }
+
+    for fighter in techs {
+        if let Ok((_, mut current, mut frame, mut velo, map, _, _, _, data, ..)) =
+            fighter_query.get_mut(fighter)
+        {
+            current.0 = *map
+                .get(&data.hit_reactions.throw_tech)
+                .expect("State with given ID doesn't exist");
+            frame.0 = 1;
+            velo.0 = Vec3::ZERO;
+        }
+    }
+}
+
+/// The height a fighter is hit at. Off the ground it's airborne, whatever its state says.
+fn hit_height(state: &State, translation: Vec3) -> StateHeight {
+    if translation.y > 0. {
+        StateHeight::Air
+    } else {
+        state.height.clone()
+    }
+}
+
+/// The reaction state and hitstun of a grounded hit. Crouching fighters take longer to recover.
+fn grounded_reaction(
+    height: &StateHeight,
+    reactions: &HitReactions,
+    hitstun: Frame,
+    crouch_hitstun: Option<Frame>,
+) -> (u16, Frame) {
+    match height {
+        StateHeight::Crouch => (
+            reactions.crouch,
+            crouch_hitstun.unwrap_or(hitstun + CROUCH_HITSTUN_BONUS),
+        ),
+        _ => (reactions.stand, hitstun),
+    }
+}
+
 pub fn hit_event_system(
     mut commands: Commands,
     mut hit_reader: EventReader<HitEvent>,
+    mut block_writer: EventWriter<BlockEvent>,
     state_query: Query<&State>,
     mut fighter_query: Query<
         (
@@ -1005,68 +1388,215 @@ pub fn hit_event_system(
             &StateMap,
             &mut Velocity,
             &Facing,
+            &Transform,
+            &InputBuffer,
+            &FighterData,
+            Option<&GroundedHitstun>,
+            Option<&AirborneHitstun>,
         ),
         With<Fighter>,
     >,
     mut hitbox_query: Query<(&mut Exclude, &HitboxData, &Owner)>,
+    mut projectile_query: Query<(&mut Exclude, &StateFrame), (With<ProjectileData>, Without<HitboxData>)>,
+    mut contact_query: Query<&mut Contact>,
+    mut absorbed_query: Query<&mut AbsorbedHits>,
+    mut juggle_query: Query<&mut Juggle>,
+    mut combo_query: Query<&mut Combo>,
+    mut meter_query: Query<&mut Meter>,
+    mut hitstop_query: Query<&mut Hitstop>,
+    mut last_counter_query: Query<&mut LastCounter>,
 ) {
-    for hit_event in hit_reader.iter() {
-        if let Ok((fighter, mut health, mut frame, mut current, map, mut velo, facing)) =
-            fighter_query.get_mut(hit_event.0.recipient)
+    let hit_events: Vec<&HitEvent> = hit_reader.iter().collect();
+
+    // The states and frames attacked from, taken before a trade puts either fighter in hitstun
+    let attacker_states: HashMap<Entity, (Entity, Frame)> = hit_events
+        .iter()
+        .filter_map(|hit_event| {
+            fighter_query
+                .get(hit_event.0.attacker)
+                .ok()
+                .map(|(_, _, frame, current, ..)| (hit_event.0.attacker, (current.0, frame.0)))
+        })
+        .collect();
+
+    for hit_event in hit_events {
+        let mut contact_kind = ContactKind::Hit;
+
+        if let Ok((
+            fighter,
+            mut health,
+            mut frame,
+            mut current,
+            map,
+            mut velo,
+            facing,
+            tf,
+            buffer,
+            data,
+            grounded_hitstun,
+            airborne_hitstun,
+        )) = fighter_query.get_mut(hit_event.0.recipient)
         {
-            health.0 = health.0.saturating_sub(hit_event.0.attacker_box.damage);
+            let attacker_box = &hit_event.0.attacker_box;
+            let reactions = &data.hit_reactions;
 
             //let s = map.get(&current.0).expect("State doesn't exist");
 
             let state = state_query
                 .get(current.0)
                 .expect("Couldn't get query of State entity");
+            let height = hit_height(state, tf.translation);
+
+            // Attacking, airborne or in hitstun fighters can't guard
+            let can_guard = state.hitboxes.is_none()
+                && !matches!(height, StateHeight::Air)
+                && grounded_hitstun.is_none()
+                && airborne_hitstun.is_none();
+            let input = buffer
+                .0
+                .get(0)
+                .map(|input| CommandInput::relative_input(input, facing.0))
+                .unwrap_or_default();
+
+            if can_guard && attacker_box.get_hit_level().blocked_by(&input) {
+                contact_kind = ContactKind::Block;
+                health.0 = health.0.saturating_sub(attacker_box.get_chip_damage());
+
+                let blockstun_id = if input.y == DirectionalInput::Negative {
+                    reactions.crouch_block
+                } else {
+                    reactions.stand_block
+                };
+
+                commands
+                    .entity(fighter)
+                    .insert(Blockstun(attacker_box.blockstun));
+
+                frame.0 = 1;
+                current.0 = *map
+                    .get(&blockstun_id)
+                    .expect("State with given ID doesn't exist");
+
+                // Velocity is along the axis to the opponent, so pushback is negative
+                velo.0 = Vec3::new(-attacker_box.block_pushback, 0., 0.);
+
+                block_writer.send(BlockEvent(hit_event.0.clone()));
+            } else if let Some(mut absorbed) = absorbed_query
+                .get_mut(fighter)
+                .ok()
+                .filter(|absorbed| absorbed.in_state(current.0) < state.armor_hits(frame.0))
+            {
+                // Armor takes the damage but keeps the fighter in its state
+                health.0 = health.0.saturating_sub(attacker_box.damage);
+
+                *absorbed = AbsorbedHits {
+                    state: Some(current.0),
+                    hits: absorbed.in_state(current.0) + 1,
+                };
+            } else {
+                let counter_hit = hit_event.0.counter_hit();
+                if counter_hit.is_some() {
+                    if let Ok(mut last_counter) = last_counter_query.get_mut(hit_event.0.attacker) {
+                        *last_counter = LastCounter {
+                            kind: hit_event.0.counter,
+                            frames: COUNTER_POPUP_FRAMES,
+                        };
+                    }
+                }
+                let extra_hitstun = counter_hit.map_or(0, |counter_hit| counter_hit.extra_hitstun);
+
+                let mut damage = counter_hit
+                    .map_or(attacker_box.damage, |counter_hit| counter_hit.damage(attacker_box.damage));
+
+                if let Ok(mut combo) = combo_query.get_mut(fighter) {
+                    // A hit on a fighter that could act starts a new combo
+                    if grounded_hitstun.is_none() && airborne_hitstun.is_none() {
+                        *combo = Combo {
+                            starter: attacker_box.starter_scaling.unwrap_or(1.),
+                            ..default()
+                        };
+                    }
 
-            match state.height {
-                StateHeight::Stand => match hit_event.0.attacker_box.on_hit {
-                    OnHit::Launch(kb) => {
-                        commands.entity(fighter).insert(AirborneHitstun);
+                    damage = combo.scale(damage, &data.combo_scaling);
+                    combo.hits += 1;
+                    combo.damage = combo.damage.saturating_add(damage);
+                }
 
-                        frame.0 = 1;
-                        current.0 = *map
-                            .get(&AIR_HITSTUN)
-                            .expect("State with given ID doesn't exist");
+                health.0 = health.0.saturating_sub(damage);
+
+                let knockback = |kb: Vec3| Vec3::new(kb.x * facing.0.sign(), kb.y, kb.z);
+
+                let juggled = juggle_query.get(fighter).map_or(0, |juggle| juggle.0);
+                let can_juggle = juggled.saturating_add(attacker_box.juggle) <= data.juggle_limit;
+
+                // In the air only launches apply, anything else resets the fighter. So does
+                // running out of juggle points.
+                let on_hit = match height {
+                    StateHeight::Air => attacker_box
+                        .on_air_hit
+                        .clone()
+                        .filter(|on_hit| matches!(on_hit, OnHit::Launch(_)) && can_juggle),
+                    _ => Some(
+                        counter_hit
+                            .and_then(|counter_hit| counter_hit.on_hit.clone())
+                            .unwrap_or_else(|| attacker_box.on_hit.clone()),
+                    ),
+                };
+
+                let reaction = match on_hit {
+                    Some(OnHit::Launch(kb)) => {
+                        commands
+                            .entity(fighter)
+                            .remove::<(GroundedHitstun, Blockstun, KnockedDown)>()
+                            .insert(AirborneHitstun::from_hit(attacker_box));
 
-                        let mut knockback = kb;
-                        knockback.x *= facing.0.sign();
-                        velo.0 = knockback;
+                        if let Ok(mut juggle) = juggle_query.get_mut(fighter) {
+                            juggle.0 = juggle.0.saturating_add(attacker_box.juggle);
+                        }
 
-                        assert!(knockback.y > 0.);
+                        velo.0 = knockback(kb);
+                        reactions.air
                     }
-                    OnHit::Grounded { kb, hitstun } => {
-                        commands.entity(fighter).insert(GroundedHitstun(hitstun));
+                    Some(OnHit::Grounded {
+                        kb,
+                        hitstun,
+                        crouch_hitstun,
+                    }) => {
+                        let (reaction, hitstun) =
+                            grounded_reaction(&height, reactions, hitstun, crouch_hitstun);
 
-                        frame.0 = 1;
-                        current.0 = *map
-                            .get(&GRND_HITSTUN_KB)
-                            .expect("State with given ID doesn't exist");
+                        commands
+                            .entity(fighter)
+                            .remove::<(Blockstun, KnockedDown)>()
+                            .insert(GroundedHitstun(hitstun + extra_hitstun));
 
-                        let mut knockback = kb;
-                        knockback.x *= facing.0.sign();
-                        velo.0 = knockback;
+                        velo.0 = knockback(kb);
+                        reaction
                     }
-                    OnHit::Stun(_stun) => {}
-                },
-                StateHeight::Crouch => todo!(),
-                StateHeight::Air => match hit_event.0.attacker_box.on_air_hit {
-                    OnHit::Launch(kb) => {
-                        frame.0 = 1;
-                        current.0 = *map
-                            .get(&AIR_HITSTUN)
-                            .expect("State with given ID doesn't exist");
-
-                        let mut knockback = kb;
-                        knockback.x *= facing.0.sign();
-                        velo.0 = knockback;
+                    Some(OnHit::Stun(stun)) => {
+                        commands
+                            .entity(fighter)
+                            .remove::<(Blockstun, KnockedDown)>()
+                            .insert(GroundedHitstun(stun + extra_hitstun));
+
+                        velo.0 = Vec3::ZERO;
+                        reactions.dizzy
                     }
-                    OnHit::Grounded { kb: _, hitstun: _ } => panic!(),
-                    OnHit::Stun(_) => panic!(),
-                },
+                    None => {
+                        commands
+                            .entity(fighter)
+                            .remove::<(GroundedHitstun, Blockstun, KnockedDown)>()
+                            .insert(AirborneHitstun::default());
+
+                        velo.0 = knockback(reactions.air_reset);
+                        reactions.air
+                    }
+                };
+
+                frame.0 = 1;
+                current.0 = *map
+                    .get(&reaction)
+                    .expect("State with given ID doesn't exist");
             }
 
             // let mut knockback = hit_event.0.attacker_box.knockback;
@@ -1075,9 +1605,69 @@ pub fn hit_event_system(
             // velo.0 = knockback;
         }
 
-        for (mut exclude, _data, owner) in hitbox_query.iter_mut() {
-            if owner.0 == hit_event.0.attacker {
-                exclude.0.insert(hit_event.0.recipient);
+        let attacker_box = &hit_event.0.attacker_box;
+        let meter_gain = |fighter: Entity| {
+            fighter_query
+                .get(fighter)
+                .ok()
+                .map(|(.., data, _, _)| (data.meter_gain.clone(), data.meter_max))
+        };
+
+        if let Some((gain, max)) = meter_gain(hit_event.0.attacker) {
+            let amount = match contact_kind {
+                ContactKind::Hit => attacker_box.meter_gain.unwrap_or(gain.hit),
+                ContactKind::Block => attacker_box.block_meter_gain.unwrap_or(gain.block),
+                ContactKind::None => 0,
+            };
+
+            if let Ok(mut meter) = meter_query.get_mut(hit_event.0.attacker) {
+                meter.gain(amount, max);
+            }
+        }
+
+        if contact_kind == ContactKind::Hit {
+            if let Some((gain, max)) = meter_gain(hit_event.0.recipient) {
+                if let Ok(mut meter) = meter_query.get_mut(hit_event.0.recipient) {
+                    meter.gain(gain.taken, max);
+                }
+            }
+        }
+
+        // Blocked hits freeze both fighters too
+        if contact_kind != ContactKind::None {
+            if let Ok(mut hitstop) = hitstop_query.get_mut(hit_event.0.recipient) {
+                hitstop.0 = hit_event.0.attacker_box.defender_hitstop;
+            }
+
+            // Fighters don't freeze when their projectile hits
+            if hit_event.0.projectile.is_none() {
+                if let Ok(mut hitstop) = hitstop_query.get_mut(hit_event.0.attacker) {
+                    hitstop.0 = hit_event.0.attacker_box.attacker_hitstop;
+                }
+            }
+        }
+
+        let Some((attacker_state, attacker_frame)) = attacker_states.get(&hit_event.0.attacker)
+        else {
+            continue;
+        };
+
+        if let Ok(mut contact) = contact_query.get_mut(hit_event.0.attacker) {
+            *contact = Contact {
+                state: Some(*attacker_state),
+                kind: contact_kind,
+            };
+        }
+
+        if let Some(projectile) = hit_event.0.projectile {
+            if let Ok((mut exclude, projectile_frame)) = projectile_query.get_mut(projectile) {
+                exclude.insert(hit_event.0.recipient, projectile_frame.0);
+            }
+        } else {
+            for (mut exclude, data, owner) in hitbox_query.iter_mut() {
+                if owner.0 == hit_event.0.attacker && data.group == hit_event.0.attacker_box.group {
+                    exclude.insert(hit_event.0.recipient, *attacker_frame);
+                }
             }
         }
     }
@@ -1126,6 +1716,59 @@ pub fn ui_lifebar_system(
     }
 }
 
+pub fn ui_meter_system(
+    mut meter_bar_query: Query<(&mut Style, &Player), With<MeterBar>>,
+    fighter_query: Query<(&Meter, &FighterData), (With<Fighter>, Changed<Meter>)>,
+    players: Res<PlayerEntities>,
+) {
+    for (mut style, player) in meter_bar_query.iter_mut() {
+        if let Ok((meter, data)) = fighter_query.get(players.get(player.0)) {
+            style.size.width = Val::Percent(meter.0 as f32 / data.meter_max as f32 * 100.);
+        }
+    }
+}
+
+/// Shows the combo each player is dealing under their lifebar
+pub fn ui_combo_system(
+    mut counter_query: Query<(&mut Text, &Player), With<ComboCounter>>,
+    fighter_query: Query<&Combo, (With<Fighter>, Changed<Combo>)>,
+    players: Res<PlayerEntities>,
+) {
+    for (mut text, player) in counter_query.iter_mut() {
+        // Combos are tracked on the fighter taking them
+        let opponent = if player.0 == 1 { 2 } else { 1 };
+
+        if let Ok(combo) = fighter_query.get(players.get(opponent)) {
+            let (hits, damage) = if combo.hits > 1 {
+                (format!("{} Hits", combo.hits), format!("\n{} Damage", combo.damage))
+            } else {
+                (String::new(), String::new())
+            };
+
+            text.sections[0].value = hits;
+            text.sections[1].value = damage;
+        }
+    }
+}
+
+/// Shows each player's last counter hit while it's still up. The popup is counted down in
+/// game frames, so a rollback can't replay or skip it.
+pub fn ui_counter_system(
+    mut popup_query: Query<(&mut Text, &Player), With<CounterPopup>>,
+    fighter_query: Query<&LastCounter, (With<Fighter>, Changed<LastCounter>)>,
+    players: Res<PlayerEntities>,
+) {
+    for (mut text, player) in popup_query.iter_mut() {
+        if let Ok(last_counter) = fighter_query.get(players.get(player.0)) {
+            text.sections[0].value = match last_counter.kind {
+                CounterKind::Counter if last_counter.frames > 0 => "COUNTER".to_string(),
+                CounterKind::Punish if last_counter.frames > 0 => "PUNISH COUNTER".to_string(),
+                _ => String::new(),
+            };
+        }
+    }
+}
+
 #[allow(clippy::type_complexity)]
 pub fn camera_system(
     mut set: ParamSet<(
@@ -1253,3 +1896,828 @@ pub fn modifier_input_check(
     //     }
     // }
 }
+
+/// Two of Ryo with all of his states, without the models, scripts and modifiers the game loads
+/// with them, for the tests of the fighter systems
+#[cfg(test)]
+struct TestFight {
+    world: bevy::prelude::World,
+    p1: Entity,
+    p2: Entity,
+}
+
+#[cfg(test)]
+impl TestFight {
+    /// Players 1 and 2 at these positions on the x axis, with the events the hit systems use
+    fn new(p1_x: f32, p2_x: f32) -> Self {
+        use bevy::prelude::{Events, World};
+
+        let mut world = World::new();
+        world.init_resource::<Events<HitEvent>>();
+        world.init_resource::<Events<BlockEvent>>();
+        world.init_resource::<Events<TransitionEvent>>();
+
+        let p1 = Self::spawn_fighter(&mut world, p1_x);
+        let p2 = Self::spawn_fighter(&mut world, p2_x);
+        world.insert_resource(PlayerEntities(p1, p2));
+
+        TestFight { world, p1, p2 }
+    }
+
+    /// Another fighter, for tests that hit more than one
+    fn spawn(&mut self, x: f32) -> Entity {
+        Self::spawn_fighter(&mut self.world, x)
+    }
+
+    fn spawn_fighter(world: &mut bevy::prelude::World, x: f32) -> Entity {
+        use super::state::SerializedState;
+
+        let serialized: Vec<SerializedState> =
+            serde_json::from_str(include_str!("../../assets/data/fighters/ryo/ryo.states"))
+                .expect("Couldn't parse ryo.states");
+        let data: FighterData =
+            serde_json::from_str(include_str!("../../assets/data/fighters/ryo/ryo.fighter"))
+                .expect("Couldn't parse ryo.fighter");
+
+        let mut map = StateMap::new();
+        let mut transitions: Vec<(Entity, Vec<u16>)> = Vec::new();
+
+        for mut state in serialized {
+            let id = state.id;
+            let hitboxes = state.unsorted_hitboxes.take();
+            let transition_ids = state.transitions.clone();
+
+            let mut state = State::from_serialized(state);
+            if let Some(hitboxes) = hitboxes {
+                let mut ordered: HashMap<u16, Vec<HitboxData>> = HashMap::new();
+                for hitbox in hitboxes {
+                    ordered.entry(hitbox.get_start_frame()).or_default().push(hitbox);
+                }
+                state.add_hitboxes(ordered);
+            }
+
+            let entity = world.spawn(state).id();
+            map.add_state(id, entity);
+            transitions.push((entity, transition_ids));
+        }
+
+        for (entity, ids) in transitions {
+            let targets = ids
+                .iter()
+                .map(|id| *map.get(id).expect("State with given ID doesn't exist"))
+                .collect();
+            world
+                .get_mut::<State>(entity)
+                .expect("Couldn't get State")
+                .transitions = targets;
+        }
+
+        let idle = *map.get(&0).expect("State with ID 0 doesn't exist");
+
+        world
+            .spawn((
+                Fighter,
+                CurrentState(idle),
+                StateFrame(1),
+                map,
+                data,
+                Velocity::default(),
+                Transform::from_xyz(x, 0., 0.),
+                InputBuffer(Buffer::with_capacity(crate::input::BUFFER_SIZE)),
+                Facing::default(),
+                Health(500),
+            ))
+            .insert((
+                Meter::default(),
+                Juggle::default(),
+                Combo::default(),
+                LastCounter::default(),
+                Contact::default(),
+                AbsorbedHits::default(),
+                Hitstop::default(),
+                PlayerAxis::default(),
+                BoneMap(HashMap::new()),
+            ))
+            .id()
+    }
+
+    fn state_entity(&self, fighter: Entity, id: u16) -> Entity {
+        *self
+            .world
+            .get::<StateMap>(fighter)
+            .and_then(|map| map.get(&id))
+            .expect("State with given ID doesn't exist")
+    }
+
+    fn set_state(&mut self, fighter: Entity, id: u16, frame: Frame) {
+        let state = self.state_entity(fighter, id);
+        self.world.get_mut::<CurrentState>(fighter).expect("Not a fighter").0 = state;
+        self.set_frame(fighter, frame);
+    }
+
+    fn set_frame(&mut self, fighter: Entity, frame: Frame) {
+        self.world.get_mut::<StateFrame>(fighter).expect("Not a fighter").0 = frame;
+    }
+
+    fn state_id(&self, fighter: Entity) -> u16 {
+        let current = self.world.get::<CurrentState>(fighter).expect("Not a fighter");
+        self.world.get::<State>(current.0).expect("Couldn't get State").id
+    }
+
+    fn buffer(&mut self, fighter: Entity, input: impl Into<u32>) {
+        self.world
+            .get_mut::<InputBuffer>(fighter)
+            .expect("Not a fighter")
+            .0
+            .insert(input.into());
+    }
+
+    /// Sends a hit from `attacker` on `recipient`, as `collision_system` would
+    fn hit(&mut self, attacker: Entity, recipient: Entity, hitbox: HitboxData) {
+        self.counter_hit(attacker, recipient, hitbox, CounterKind::None);
+    }
+
+    fn counter_hit(&mut self, attacker: Entity, recipient: Entity, hitbox: HitboxData, counter: CounterKind) {
+        use bevy::prelude::Events;
+
+        self.world.resource_mut::<Events<HitEvent>>().send(HitEvent(CollisionData {
+            attacker_box: hitbox,
+            attacker,
+            projectile: None,
+            recipient_box: HurtboxData::default(),
+            recipient,
+            counter,
+        }));
+    }
+
+    /// The ID of the state the fighter would transition to, the same way `process_input_system` picks it
+    fn next_state(&mut self, fighter: Entity) -> Option<u16> {
+        use bevy::ecs::system::SystemState;
+
+        let world = &mut self.world;
+        let mut system_state: SystemState<(
+            Query<(Entity, &State)>,
+            Query<(
+                &CurrentState,
+                &StateFrame,
+                &InputBuffer,
+                &Facing,
+                &Transform,
+                &Velocity,
+                &Health,
+                &Meter,
+                &PlayerAxis,
+                &Contact,
+            )>,
+        )> = SystemState::new(world);
+        let (state_query, fighter_query) = system_state.get(world);
+
+        let (current, frame, buffer, facing, tf, velo, health, meter, axis, contact) =
+            fighter_query.get(fighter).expect("Not a fighter");
+        let (_, state) = state_query.get(current.0).expect("Couldn't get State");
+
+        let context = ConditionContext {
+            current_id: state.id,
+            state,
+            frame: frame.0,
+            buffer: &buffer.0,
+            facing: facing.0,
+            transform: tf,
+            velocity: velo,
+            health: health.0,
+            meter: meter.0,
+            axis,
+            opponent_state: None,
+            input_met: None,
+        };
+
+        next_state(&state_query, contact.in_state(current.0), &context).map(|(_, to_state)| to_state.id)
+    }
+}
+
+#[test]
+fn teched_throw_returns_to_neutral() {
+    use super::state::{THROWN, THROW_TECH};
+    use crate::input::{ButtonPress, StateInput};
+    use bevy::prelude::Schedule;
+
+    let mut fight = TestFight::new(0., 0.5);
+    let (thrower, defender) = (fight.p1, fight.p2);
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(throw_system);
+
+    // Ryo's throw grabs on frames 3 to 5
+    fight.set_state(thrower, 500, 3);
+    schedule.run(&mut fight.world);
+
+    assert_eq!(fight.state_id(thrower), 501);
+    assert_eq!(fight.state_id(defender), THROWN);
+    assert_eq!(fight.world.get::<Grabbed>(defender).unwrap().by, Some(thrower));
+
+    fight.buffer(
+        defender,
+        StateInput {
+            a: ButtonPress::Press,
+            j: ButtonPress::Press,
+            ..Default::default()
+        },
+    );
+    fight.set_frame(defender, 2);
+    schedule.run(&mut fight.world);
+
+    assert_eq!(fight.state_id(thrower), THROW_TECH);
+    assert_eq!(fight.state_id(defender), THROW_TECH);
+    assert!(fight.world.get::<Grabbed>(defender).is_none());
+
+    // Both go back to idle once the tech's duration is over
+    for fighter in [thrower, defender] {
+        fight.set_frame(fighter, 20);
+        assert_eq!(fight.next_state(fighter), None);
+
+        fight.set_frame(fighter, 21);
+        assert_eq!(fight.next_state(fighter), Some(0));
+    }
+}
+
+#[test]
+fn ground_throws_miss_fighters_off_the_ground() {
+    use bevy::prelude::Schedule;
+
+    let mut fight = TestFight::new(0., 0.5);
+    let (thrower, defender) = (fight.p1, fight.p2);
+
+    // Idle, but above the floor, like in one of the jump states
+    fight.world.get_mut::<Transform>(defender).unwrap().translation.y = 1.;
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(throw_system);
+
+    fight.set_state(thrower, 500, 3);
+    schedule.run(&mut fight.world);
+
+    assert_eq!(fight.state_id(thrower), 500);
+    assert_eq!(fight.state_id(defender), 0);
+    assert!(fight.world.get::<Grabbed>(defender).is_none());
+}
+
+#[test]
+fn soft_knockdown_wakes_up_to_neutral() {
+    use super::state::{AIR_HITSTUN, BACK_RISE, QUICK_RISE, SOFT_KNOCKDOWN};
+    use crate::input::{ButtonPress, StateInput};
+    use bevy::prelude::Schedule;
+
+    let mut fight = TestFight::new(0., 1.);
+    let (quick, back) = (fight.p1, fight.p2);
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(hitstun_system);
+
+    // Both land from a launch
+    for fighter in [quick, back] {
+        fight.set_state(fighter, AIR_HITSTUN, 10);
+        fight.world.entity_mut(fighter).insert(AirborneHitstun {
+            knockdown: Some(Knockdown::Soft(24)),
+            ..default()
+        });
+    }
+    schedule.run(&mut fight.world);
+
+    for fighter in [quick, back] {
+        assert_eq!(fight.state_id(fighter), SOFT_KNOCKDOWN);
+        assert!(fight.world.get::<AirborneHitstun>(fighter).is_none());
+        assert!(fight.world.get::<KnockedDown>(fighter).is_some());
+    }
+
+    fight.buffer(quick, StateInput::lk_bits(true));
+    // Back is held while pressing J
+    fight.buffer(
+        back,
+        StateInput {
+            j: ButtonPress::Press,
+            ..StateInput::left(false)
+        },
+    );
+    schedule.run(&mut fight.world);
+
+    assert_eq!(fight.state_id(quick), QUICK_RISE);
+    assert_eq!(fight.state_id(back), BACK_RISE);
+
+    for (fighter, duration) in [(quick, 12), (back, 24)] {
+        assert!(fight.world.get::<KnockedDown>(fighter).is_none());
+
+        fight.set_frame(fighter, duration + 1);
+        assert_eq!(fight.next_state(fighter), Some(0));
+    }
+}
+
+#[test]
+fn juggle_presses_dont_quick_rise() {
+    use super::state::{AIR_HITSTUN, QUICK_RISE, SOFT_KNOCKDOWN};
+    use crate::input::{CommandInput, StateInput};
+    use bevy::prelude::Schedule;
+
+    let mut fight = TestFight::new(0., 1.);
+    let fighter = fight.p1;
+    // Buffered long enough to still see a press from before landing
+    fight.world.get_mut::<FighterData>(fighter).unwrap().wakeup.quick_rise =
+        serde_json::from_str::<CommandInput>(r#"{"notation": "J", "bufferTime": 8}"#).unwrap();
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(hitstun_system);
+
+    fight.set_state(fighter, AIR_HITSTUN, 10);
+    fight.world.entity_mut(fighter).insert(AirborneHitstun {
+        knockdown: Some(Knockdown::Soft(24)),
+        ..default()
+    });
+    fight.buffer(fighter, StateInput::lk_bits(true));
+    fight.buffer(fighter, StateInput::default());
+    schedule.run(&mut fight.world);
+    assert_eq!(fight.state_id(fighter), SOFT_KNOCKDOWN);
+
+    fight.buffer(fighter, StateInput::default());
+    fight.set_frame(fighter, 2);
+    schedule.run(&mut fight.world);
+    assert_eq!(fight.state_id(fighter), SOFT_KNOCKDOWN);
+
+    fight.buffer(fighter, StateInput::lk_bits(true));
+    fight.set_frame(fighter, 3);
+    schedule.run(&mut fight.world);
+    assert_eq!(fight.state_id(fighter), QUICK_RISE);
+}
+
+#[test]
+fn hard_knockdown_has_no_wakeup_options() {
+    use super::state::HARD_KNOCKDOWN;
+    use crate::input::StateInput;
+    use bevy::prelude::Schedule;
+
+    let mut fight = TestFight::new(0., 1.);
+    let fighter = fight.p1;
+    fight.set_state(fighter, HARD_KNOCKDOWN, 40);
+    fight.world.entity_mut(fighter).insert(KnockedDown(Knockdown::Hard(40)));
+    fight.buffer(fighter, StateInput::lk_bits(true));
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(hitstun_system);
+
+    schedule.run(&mut fight.world);
+    assert_eq!(fight.state_id(fighter), HARD_KNOCKDOWN);
+
+    fight.set_frame(fighter, 41);
+    schedule.run(&mut fight.world);
+    assert_eq!(fight.state_id(fighter), 0);
+    assert!(fight.world.get::<KnockedDown>(fighter).is_none());
+}
+
+#[test]
+fn reactions_follow_the_height_hit_at() {
+    use super::state::{AIR_HITSTUN, CROUCH_HITSTUN, GRND_HITSTUN_KB};
+    use bevy::prelude::Schedule;
+
+    let reactions = HitReactions::default();
+    assert_eq!(
+        grounded_reaction(&StateHeight::Stand, &reactions, 18, Some(25)),
+        (GRND_HITSTUN_KB, 18)
+    );
+    assert_eq!(
+        grounded_reaction(&StateHeight::Crouch, &reactions, 18, Some(25)),
+        (CROUCH_HITSTUN, 25)
+    );
+
+    let mut fight = TestFight::new(0., 1.);
+    let (attacker, crouching) = (fight.p1, fight.p2);
+    let airborne = fight.spawn(-1.);
+
+    fight.set_state(crouching, 20, 1);
+    // Off the ground, even though idle doesn't say it's in the air
+    fight.world.get_mut::<Transform>(airborne).unwrap().translation.y = 1.;
+
+    let hitbox = HitboxData {
+        damage: 50,
+        on_hit: OnHit::Grounded {
+            kb: Vec3::X,
+            hitstun: 18,
+            crouch_hitstun: None,
+        },
+        ..default()
+    };
+    for recipient in [crouching, airborne] {
+        fight.hit(attacker, recipient, hitbox.clone());
+    }
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(hit_event_system);
+    schedule.run(&mut fight.world);
+
+    assert_eq!(fight.state_id(crouching), CROUCH_HITSTUN);
+    assert_eq!(
+        fight.world.get::<GroundedHitstun>(crouching).unwrap().0,
+        18 + CROUCH_HITSTUN_BONUS
+    );
+
+    // A grounded only hit resets fighters in the air
+    assert_eq!(fight.state_id(airborne), AIR_HITSTUN);
+    assert!(fight.world.get::<AirborneHitstun>(airborne).is_some());
+}
+
+#[test]
+fn projectile_ties_go_to_the_lowest_rollback_id() {
+    let hitbox = |priority, global_id| HitboxData {
+        priority,
+        global_id,
+        ..default()
+    };
+
+    let mut candidates = vec![
+        (hitbox(1, None), Some(9)),
+        (hitbox(1, Some(4)), None),
+        (hitbox(2, Some(7)), None),
+        (hitbox(1, None), Some(3)),
+    ];
+    candidates.sort_by_key(|(hitbox, projectile)| candidate_order(hitbox, *projectile));
+
+    let order: Vec<_> = candidates
+        .iter()
+        .map(|(hitbox, projectile)| (hitbox.priority, hitbox.global_id, *projectile))
+        .collect();
+    assert_eq!(
+        order,
+        vec![
+            (2, Some(7), None),
+            (1, None, Some(3)),
+            (1, None, Some(9)),
+            (1, Some(4), None),
+        ]
+    );
+}
+
+#[test]
+fn hits_on_the_same_frame_trade() {
+    use super::state::GRND_HITSTUN_KB;
+    use bevy::prelude::{GlobalTransform, IntoSystemConfigs, Schedule};
+    use parry3d::shape::Capsule;
+
+    // Either player being checked first gives the same result
+    for swapped in [false, true] {
+        let mut fight = TestFight::new(0., 2.);
+        let (p1, p2) = (fight.p1, fight.p2);
+        if swapped {
+            fight.world.insert_resource(PlayerEntities(p2, p1));
+        }
+
+        for (fighter, x) in [(p1, 0.), (p2, 2.)] {
+            let hurtbox = fight
+                .world
+                .spawn((HurtboxData::default(), Collider::default(), GlobalTransform::from_xyz(x, 0., 0.)))
+                .id();
+            let mut hurtboxes = Hurtboxes::new();
+            hurtboxes.add(hurtbox);
+            fight
+                .world
+                .entity_mut(fighter)
+                .insert((hurtboxes, ActiveHurtboxes::default()));
+        }
+
+        // Each fighter's hitbox is inside the other one
+        for (attacker, x, damage, global_id) in [(p1, 2., 60, 0), (p2, 0., 40, 1)] {
+            fight.world.spawn((
+                HitboxData {
+                    damage,
+                    global_id: Some(global_id),
+                    ..default()
+                },
+                Owner(attacker),
+                Exclude::default(),
+                Collider::from(Capsule::new_y(0.2, 0.2)),
+                GlobalTransform::from_xyz(x, 0., 0.),
+            ));
+        }
+
+        let mut schedule = Schedule::new();
+        schedule.add_systems((collision_system, hit_event_system).chain());
+        schedule.run(&mut fight.world);
+
+        assert_eq!(fight.world.get::<Health>(p1).unwrap().0, 460);
+        assert_eq!(fight.world.get::<Health>(p2).unwrap().0, 440);
+        assert_eq!(fight.state_id(p1), GRND_HITSTUN_KB);
+        assert_eq!(fight.state_id(p2), GRND_HITSTUN_KB);
+    }
+}
+
+#[test]
+fn launches_past_the_juggle_limit_reset() {
+    use super::state::AIR_HITSTUN;
+    use bevy::prelude::Schedule;
+
+    let mut fight = TestFight::new(0., 1.);
+    let (attacker, fresh) = (fight.p1, fight.p2);
+    let juggled = fight.spawn(-1.);
+
+    for fighter in [fresh, juggled] {
+        fight.set_state(fighter, AIR_HITSTUN, 5);
+        fight.world.get_mut::<Transform>(fighter).unwrap().translation.y = 1.;
+        fight.world.entity_mut(fighter).insert(AirborneHitstun::default());
+    }
+
+    // One more launch takes the juggled fighter past its limit
+    let limit = fight.world.get::<FighterData>(juggled).unwrap().juggle_limit;
+    fight.world.get_mut::<Juggle>(juggled).unwrap().0 = limit;
+
+    let hitbox = HitboxData {
+        damage: 30,
+        on_air_hit: Some(OnHit::Launch(Vec3::new(1., 4., 0.))),
+        juggle: 1,
+        knockdown: Knockdown::Hard(40),
+        ..default()
+    };
+    for recipient in [fresh, juggled] {
+        fight.hit(attacker, recipient, hitbox.clone());
+    }
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(hit_event_system);
+    schedule.run(&mut fight.world);
+
+    assert_eq!(fight.world.get::<Juggle>(fresh).unwrap().0, 1);
+    assert_eq!(
+        fight.world.get::<AirborneHitstun>(fresh).unwrap().knockdown,
+        Some(Knockdown::Hard(40))
+    );
+    assert_eq!(fight.world.get::<Velocity>(fresh).unwrap().0, Vec3::new(1., 4., 0.));
+
+    // Reset instead, without taking any more juggle points or knocking down on landing
+    let air_reset = fight.world.get::<FighterData>(juggled).unwrap().hit_reactions.air_reset;
+    assert_eq!(fight.state_id(juggled), AIR_HITSTUN);
+    assert_eq!(fight.world.get::<Juggle>(juggled).unwrap().0, limit);
+    assert_eq!(fight.world.get::<AirborneHitstun>(juggled).unwrap().knockdown, None);
+    assert_eq!(fight.world.get::<Velocity>(juggled).unwrap().0, air_reset);
+}
+
+#[test]
+fn meter_gated_states_need_their_cost() {
+    use crate::input::{ButtonPress, StateInput};
+
+    let mut fight = TestFight::new(0., 1.);
+    let fighter = fight.p1;
+
+    // 5C costs meter for this test
+    let heavy = fight.state_entity(fighter, 300);
+    fight.world.get_mut::<State>(heavy).unwrap().meter_cost = 50;
+
+    // Ryo's 5C is on b
+    fight.buffer(
+        fighter,
+        StateInput {
+            b: ButtonPress::Press,
+            ..Default::default()
+        },
+    );
+
+    fight.world.get_mut::<Meter>(fighter).unwrap().0 = 49;
+    assert_ne!(fight.next_state(fighter), Some(300));
+
+    fight.world.get_mut::<Meter>(fighter).unwrap().0 = 50;
+    assert_eq!(fight.next_state(fighter), Some(300));
+}
+
+#[test]
+fn hitstop_freezes_both_fighters() {
+    use bevy::prelude::{Events, IntoSystemConfigs, Schedule};
+
+    let mut fight = TestFight::new(0., 1.);
+    let (attacker, recipient) = (fight.p1, fight.p2);
+
+    // Something the attacker owns, like a projectile
+    let object = fight
+        .world
+        .spawn((StateFrame(1), Owner(attacker), Active::default()))
+        .id();
+
+    let hitbox = HitboxData {
+        damage: 30,
+        on_hit: OnHit::Grounded {
+            kb: Vec3::new(2., 3., 0.),
+            hitstun: 18,
+            crouch_hitstun: None,
+        },
+        attacker_hitstop: 2,
+        defender_hitstop: 3,
+        ..default()
+    };
+    fight.hit(attacker, recipient, hitbox);
+
+    let mut hit = Schedule::new();
+    hit.add_system(hit_event_system);
+    hit.run(&mut fight.world);
+
+    assert_eq!(fight.world.get::<Hitstop>(attacker).unwrap().0, 2);
+    assert_eq!(fight.world.get::<Hitstop>(recipient).unwrap().0, 3);
+
+    let mut frame = Schedule::new();
+    frame.add_systems((transition_system, increment_frame_system, movement_system, hitstop_system).chain());
+
+    let frames = |world: &bevy::prelude::World| {
+        [attacker, object, recipient].map(|entity| world.get::<StateFrame>(entity).unwrap().0)
+    };
+
+    // A transition asked for during hitstop doesn't happen
+    fight.world.resource_mut::<Events<TransitionEvent>>().send(TransitionEvent::new(attacker, 100));
+    frame.run(&mut fight.world);
+    frame.run(&mut fight.world);
+    assert_eq!(frames(&fight.world), [1, 1, 1]);
+    assert_eq!(fight.state_id(attacker), 0);
+    assert_eq!(fight.world.get::<Transform>(recipient).unwrap().translation.y, 0.);
+
+    // The attacker and its object come out of hitstop a frame before the recipient
+    frame.run(&mut fight.world);
+    assert_eq!(frames(&fight.world), [2, 2, 1]);
+    assert_eq!(fight.world.get::<Transform>(recipient).unwrap().translation.y, 0.);
+
+    frame.run(&mut fight.world);
+    assert_eq!(frames(&fight.world), [3, 3, 2]);
+    assert!(fight.world.get::<Transform>(recipient).unwrap().translation.y > 0.);
+    assert!(!fight.world.get::<Hitstop>(recipient).unwrap().is_active());
+
+    fight.world.resource_mut::<Events<TransitionEvent>>().send(TransitionEvent::new(attacker, 100));
+    frame.run(&mut fight.world);
+    assert_eq!(fight.state_id(attacker), 100);
+}
+
+#[test]
+fn blocked_hits_freeze_both_fighters() {
+    use crate::input::StateInput;
+    use bevy::prelude::Schedule;
+
+    let mut fight = TestFight::new(0., 1.);
+    let (attacker, defender) = (fight.p1, fight.p2);
+    fight.buffer(defender, StateInput::left(false));
+
+    fight.hit(
+        attacker,
+        defender,
+        HitboxData {
+            damage: 30,
+            blockstun: 12,
+            attacker_hitstop: 5,
+            defender_hitstop: 6,
+            ..default()
+        },
+    );
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(hit_event_system);
+    schedule.run(&mut fight.world);
+
+    assert!(fight.world.get::<Blockstun>(defender).is_some());
+    assert_eq!(fight.world.get::<Hitstop>(attacker).unwrap().0, 5);
+    assert_eq!(fight.world.get::<Hitstop>(defender).unwrap().0, 6);
+}
+
+#[test]
+fn whiff_cancels_open_once_the_hitboxes_are_over() {
+    use super::state::{CancelOn, CancelRoute};
+    use crate::input::{ButtonPress, StateInput};
+
+    let mut fight = TestFight::new(0., 1.);
+    let fighter = fight.p1;
+
+    // 5A, whose hitbox is out on frames 5 to 10, whiff cancels into 5C for this test
+    let (light, heavy) = (fight.state_entity(fighter, 100), fight.state_entity(fighter, 300));
+    fight.world.get_mut::<State>(light).unwrap().cancels = vec![CancelRoute {
+        on: vec![CancelOn::Whiff],
+        targets: vec![heavy],
+        ..Default::default()
+    }];
+
+    fight.buffer(
+        fighter,
+        StateInput {
+            b: ButtonPress::Press,
+            ..Default::default()
+        },
+    );
+
+    // Not while the hitbox can still hit
+    fight.set_state(fighter, 100, 10);
+    assert_eq!(fight.next_state(fighter), None);
+
+    fight.set_frame(fighter, 11);
+    assert_eq!(fight.next_state(fighter), Some(300));
+
+    // Nor once it's connected
+    *fight.world.get_mut::<Contact>(fighter).unwrap() = Contact {
+        state: Some(light),
+        kind: ContactKind::Hit,
+    };
+    assert_eq!(fight.next_state(fighter), None);
+}
+
+#[test]
+fn counter_hits_stay_up_for_game_frames() {
+    use bevy::prelude::{IntoSystemConfigs, Schedule};
+
+    let mut fight = TestFight::new(0., 1.);
+    let (p1, p2) = (fight.p1, fight.p2);
+
+    let mut schedule = Schedule::new();
+    schedule.add_systems((last_counter_system, hit_event_system).chain());
+
+    fight.counter_hit(p1, p2, HitboxData::default(), CounterKind::Punish);
+    schedule.run(&mut fight.world);
+
+    let last_counter = fight.world.get::<LastCounter>(p1).unwrap();
+    assert_eq!(last_counter.kind, CounterKind::Punish);
+    assert_eq!(last_counter.frames, COUNTER_POPUP_FRAMES);
+    assert_eq!(fight.world.get::<LastCounter>(p2).unwrap().frames, 0);
+
+    // Regular hits leave it counting down
+    fight.hit(p1, p2, HitboxData::default());
+    schedule.run(&mut fight.world);
+    assert_eq!(fight.world.get::<LastCounter>(p1).unwrap().frames, COUNTER_POPUP_FRAMES - 1);
+
+    for _ in 0..COUNTER_POPUP_FRAMES {
+        schedule.run(&mut fight.world);
+    }
+    assert_eq!(fight.world.get::<LastCounter>(p1).unwrap().frames, 0);
+}
+
+#[test]
+fn state_hurtboxes_replace_the_model_ones_until_the_state_ends() {
+    use crate::battle::HurtboxMaterial;
+    use bevy::prelude::{apply_system_buffers, Events, GlobalTransform, Handle, IntoSystemConfigs, Schedule, World};
+    use parry3d::shape::Capsule;
+
+    let mut fight = TestFight::new(0., 1.);
+    let (attacker, defender) = (fight.p1, fight.p2);
+    let capsule = || Collider::from(Capsule::new_y(0.2, 0.2));
+
+    // The model hurtbox is on the defender, and the state one is spawned back at the origin
+    let model = fight
+        .world
+        .spawn((HurtboxData::default(), capsule(), GlobalTransform::from_xyz(1., 0., 0.)))
+        .id();
+    let mut hurtboxes = Hurtboxes::new();
+    hurtboxes.add(model);
+    fight.world.entity_mut(defender).insert((hurtboxes, ActiveHurtboxes::default()));
+    fight.world.entity_mut(attacker).insert((Hurtboxes::new(), ActiveHurtboxes::default()));
+
+    let mut hurtbox = HurtboxData::default();
+    hurtbox.global_id = Some(7);
+    hurtbox.end_frame = Some(5);
+    let idle = fight.state_entity(defender, 0);
+    let mut state = fight.world.get_mut::<State>(idle).unwrap();
+    state.hurtboxes = Some([(1, vec![hurtbox])].into_iter().collect());
+    state.replace_hurtboxes = true;
+
+    fight.world.insert_resource(HitboxMap([(7, (Handle::default(), capsule()))].into_iter().collect()));
+    fight.world.insert_resource(HurtboxMaterial(Handle::default()));
+    fight.world.insert_resource(RollbackIdProvider::default());
+
+    // Only reaches the model hurtbox
+    fight.world.spawn((
+        HitboxData::default(),
+        Owner(attacker),
+        Exclude::default(),
+        capsule(),
+        GlobalTransform::from_xyz(1., 0., 0.),
+    ));
+
+    let mut schedule = Schedule::new();
+    schedule.add_systems(
+        (
+            hurtbox_component_system,
+            apply_system_buffers,
+            hurtbox_removal_system,
+            apply_system_buffers,
+            collision_system,
+        )
+            .chain(),
+    );
+
+    let state_hurtboxes = |world: &mut World| {
+        world
+            .query::<(&HurtboxData, &Owner)>()
+            .iter(world)
+            .filter(|(_, owner)| owner.0 == defender)
+            .count()
+    };
+
+    fight.set_state(defender, 0, 1);
+    schedule.run(&mut fight.world);
+    assert_eq!(fight.world.get::<ActiveHurtboxes>(defender).unwrap().list, vec![7]);
+    assert_eq!(state_hurtboxes(&mut fight.world), 1);
+    assert!(fight.world.resource::<Events<HitEvent>>().is_empty());
+
+    // Added to the model hurtbox instead, the hit connects
+    fight.world.get_mut::<State>(idle).unwrap().replace_hurtboxes = false;
+    schedule.run(&mut fight.world);
+    assert_eq!(state_hurtboxes(&mut fight.world), 1);
+    assert!(!fight.world.resource::<Events<HitEvent>>().is_empty());
+
+    fight.set_state(defender, 20, 2);
+    schedule.run(&mut fight.world);
+    assert!(fight.world.get::<ActiveHurtboxes>(defender).unwrap().list.is_empty());
+    assert_eq!(state_hurtboxes(&mut fight.world), 0);
+}
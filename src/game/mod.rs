@@ -1,4 +1,5 @@
 pub mod debug;
+pub mod synctest;
 
 use std::default;
 
@@ -10,11 +11,15 @@ use bevy::{
 use bevy_common_assets::json::JsonAssetPlugin;
 
 use crate::fighter::animation::components::FullBoneTransformMap;
+use crate::input::bindings::{load_input_configs, InputConfig, Rebinding};
 use crate::util::pickle_asset_loader::PickleAssetPlugin;
 use crate::{insert_meshes, startup, GameDebug};
 
 use crate::{
-    battle::{create_battle_ui, extra_setup_system, load_fighters, loading_wait, spawn_fighters},
+    battle::{
+        create_battle_ui, extra_setup_system, load_fighters, loading_wait, spawn_fighters,
+        stage::{load_stage, Stage},
+    },
     fighter::{self, systems::SetupSet, data::FighterData, systems::NonRollbackSet},
     SerializedStateVec
 };
@@ -181,8 +186,16 @@ impl Plugin for SetupPlugin {
              ]))
             .add_plugin(JsonAssetPlugin::<FighterData>::new(&["json", "fighter"]))
             .add_plugin(PickleAssetPlugin::<FullBoneTransformMap>::new(&["hurt"]))
+            .add_plugin(JsonAssetPlugin::<InputConfig>::new(&["input"]))
+            .add_plugin(JsonAssetPlugin::<Stage>::new(&["stage"]))
             .add_systems(
-                (load_fighters, create_battle_ui, apply_system_buffers)
+                (
+                    load_fighters,
+                    load_input_configs,
+                    load_stage,
+                    create_battle_ui,
+                    apply_system_buffers,
+                )
                     .chain()
                     .in_set(SetupSet::Setup),
             )
@@ -248,6 +261,7 @@ impl Plugin for SetupPlugin {
             ))
             .insert_resource(RollbackIdProvider::default())
             .insert_resource(GameDebug(false))
+            .init_resource::<Rebinding>()
             
             
             ;
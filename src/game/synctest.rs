@@ -0,0 +1,305 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use bevy::{
+    prelude::*,
+    reflect::Reflect,
+    utils::{get_short_name, HashMap},
+};
+use bevy_ggrs::{GGRSSchedule, Rollback};
+
+use crate::fighter::{state::Health, systems::RollbackSet};
+
+// How many frames of checksums are kept around to compare resimulated frames against
+const HISTORY_FRAMES: u32 = 128;
+
+/// Frames advanced since the session started. Registered for rollback, so after a
+/// load it tells us which frame is being resimulated.
+#[derive(Default, Debug, Clone, Copy, Reflect, Resource)]
+pub struct RollbackFrame(pub u32);
+
+type ChecksumFn = Arc<dyn Fn(&World, Entity) -> Option<u64> + Send + Sync>;
+
+/// The rollback components that get checked for mismatches between the original
+/// and resimulated runs of a frame.
+#[derive(Default, Clone, Resource)]
+pub struct ChecksumComponents(Vec<(String, ChecksumFn)>);
+
+impl ChecksumComponents {
+    pub fn track<T: Component + Debug>(self) -> Self {
+        self.track_with::<T>(debug_checksum)
+    }
+
+    /// Tracks a component with its own checksum. Components holding a hash collection need one
+    /// that doesn't depend on iteration order, since a restored copy can iterate differently.
+    pub fn track_with<T: Component>(mut self, checksum: fn(&T) -> u64) -> Self {
+        self.0.push((
+            get_short_name(std::any::type_name::<T>()),
+            Arc::new(move |world, entity| world.get::<T>(entity).map(checksum)),
+        ));
+        self
+    }
+}
+
+fn debug_checksum<T: Debug>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", value).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Checksum of a collection's entries that's the same whatever order they come in
+pub fn unordered_checksum<T: Debug>(entries: impl Iterator<Item = T>) -> u64 {
+    entries
+        .map(|entry| debug_checksum(&entry))
+        .fold(0, u64::wrapping_add)
+}
+
+/// Checksums of every tracked component, per frame and rollback ID
+#[derive(Default, Resource)]
+pub struct ChecksumHistory(BTreeMap<u32, HashMap<u32, Vec<Option<u64>>>>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Desync {
+    pub frame: u32,
+    pub entity: String,
+    pub components: Vec<String>,
+}
+
+/// Every mismatch found so far
+#[derive(Default, Resource)]
+pub struct DesyncReport(pub Vec<Desync>);
+
+/// Records the checksums of the tracked components for the current frame. If the frame was
+/// already recorded, this is a resimulation, and any component that changed is reported.
+pub fn checksum_system(world: &mut World) {
+    let frame = world.resource::<RollbackFrame>().0;
+
+    let mut rollback_query = world.query::<(Entity, &Rollback, Option<&Name>)>();
+    let tracked = world.resource::<ChecksumComponents>();
+
+    let mut names: HashMap<u32, String> = HashMap::new();
+    let mut current: HashMap<u32, Vec<Option<u64>>> = HashMap::new();
+
+    for (entity, rollback, name) in rollback_query.iter(world) {
+        let checksums = tracked
+            .0
+            .iter()
+            .map(|(_, checksum)| checksum(world, entity))
+            .collect();
+
+        current.insert(rollback.id(), checksums);
+        names.insert(
+            rollback.id(),
+            name.map_or_else(|| format!("Rollback {}", rollback.id()), |name| name.to_string()),
+        );
+    }
+
+    let mut desyncs = Vec::new();
+    let recorded = world.resource::<ChecksumHistory>().0.get(&frame);
+
+    if let Some(recorded) = recorded {
+        // Entities that only exist in one of the runs (e.g. respawned hitboxes) can't be compared
+        for (id, checksums) in current.iter() {
+            if let Some(original) = recorded.get(id) {
+                let components: Vec<String> = tracked
+                    .0
+                    .iter()
+                    .zip(original.iter().zip(checksums.iter()))
+                    .filter(|(_, (original, resimulated))| original != resimulated)
+                    .map(|((name, _), _)| name.clone())
+                    .collect();
+
+                if !components.is_empty() {
+                    desyncs.push(Desync {
+                        frame,
+                        entity: names[id].clone(),
+                        components,
+                    });
+                }
+            }
+        }
+    }
+
+    if recorded.is_none() {
+        let mut history = world.resource_mut::<ChecksumHistory>();
+        history.0.insert(frame, current);
+
+        let oldest = frame.saturating_sub(HISTORY_FRAMES);
+        history.0 = history.0.split_off(&oldest);
+    }
+
+    for desync in desyncs.iter() {
+        error!(
+            "Desync on frame {}: {} differed in {:?}",
+            desync.frame, desync.entity, desync.components
+        );
+    }
+
+    world.resource_mut::<DesyncReport>().0.extend(desyncs);
+    world.resource_mut::<RollbackFrame>().0 += 1;
+}
+
+/// Checks every frame resimulated by a `SyncTestSession` against its original run.
+/// The `RollbackFrame` resource still has to be registered for rollback with the `GGRSPlugin`.
+pub struct SyncTestPlugin(pub ChecksumComponents);
+
+impl Plugin for SyncTestPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.0.clone())
+            .init_resource::<RollbackFrame>()
+            .init_resource::<ChecksumHistory>()
+            .init_resource::<DesyncReport>()
+            .add_system(
+                checksum_system
+                    .after(RollbackSet::Stage5)
+                    .in_schedule(GGRSSchedule),
+            );
+    }
+}
+
+#[test]
+fn resimulated_mismatch_is_reported() {
+    let mut world = World::new();
+    world.insert_resource(ChecksumComponents::default().track::<Health>());
+    world.init_resource::<RollbackFrame>();
+    world.init_resource::<ChecksumHistory>();
+    world.init_resource::<DesyncReport>();
+
+    let fighter = world
+        .spawn((Rollback::new(0), Health(500), Name::new("Player 1")))
+        .id();
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(checksum_system);
+
+    // Original run of frame 0
+    schedule.run(&mut world);
+    assert!(world.resource::<DesyncReport>().0.is_empty());
+
+    // Load frame 0 and resimulate it the same way
+    world.resource_mut::<RollbackFrame>().0 = 0;
+    schedule.run(&mut world);
+    assert!(world.resource::<DesyncReport>().0.is_empty());
+
+    // Load frame 0 again, but resimulate it differently
+    world.resource_mut::<RollbackFrame>().0 = 0;
+    world.get_mut::<Health>(fighter).unwrap().0 = 470;
+    schedule.run(&mut world);
+
+    assert_eq!(
+        world.resource::<DesyncReport>().0,
+        vec![Desync {
+            frame: 0,
+            entity: "Player 1".to_string(),
+            components: vec!["Health".to_string()],
+        }]
+    );
+}
+
+#[test]
+fn hash_collections_checksum_in_any_order() {
+    use crate::fighter::state::{Exclude, Frame};
+
+    let mut forward = Exclude::default();
+    let mut backward = Exclude::default();
+    for id in 0..16 {
+        forward.0.insert(Entity::from_raw(id), id as Frame);
+        backward.0.insert(Entity::from_raw(15 - id), (15 - id) as Frame);
+    }
+
+    assert_eq!(
+        unordered_checksum(forward.0.iter()),
+        unordered_checksum(backward.0.iter())
+    );
+
+    backward.0.insert(Entity::from_raw(3), 4);
+    assert_ne!(
+        unordered_checksum(forward.0.iter()),
+        unordered_checksum(backward.0.iter())
+    );
+}
+
+#[test]
+fn synctest_session_runs_without_mismatches() {
+    use std::time::{Duration, Instant};
+
+    use bevy::{
+        core::{TaskPoolPlugin, TypeRegistrationPlugin},
+        ecs::system::In,
+    };
+    use bevy_ggrs::{GGRSPlugin, RollbackIdProvider, Session};
+    use ggrs::{PlayerHandle, PlayerType, SessionBuilder};
+
+    use crate::{
+        fighter::state::{Exclude, Frame},
+        input::Input,
+        GGRSConfig,
+    };
+
+    fn no_input(_: In<PlayerHandle>) -> Input {
+        Input(0)
+    }
+
+    // Fills a hash map every frame, so restored copies get the chance to iterate differently
+    fn exclude_system(frame: Res<RollbackFrame>, mut query: Query<(&mut Exclude, &mut Health)>) {
+        for (mut exclude, mut health) in query.iter_mut() {
+            exclude.0.insert(Entity::from_raw(frame.0), frame.0 as Frame);
+            health.0 -= 1;
+        }
+    }
+
+    const FPS: u32 = 60;
+    const FRAMES: u32 = 10;
+
+    // Time is stepped by hand rather than by the clock, so every run advances the same frames
+    let mut app = App::new();
+    app.add_plugin(TaskPoolPlugin::default())
+        .add_plugin(TypeRegistrationPlugin::default())
+        .init_resource::<Time>();
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(FPS as usize)
+        .with_input_system(no_input)
+        .register_rollback_component::<Health>()
+        .register_rollback_component::<Exclude>()
+        .register_rollback_resource::<RollbackFrame>()
+        .build(&mut app);
+
+    app.add_plugin(SyncTestPlugin(
+        ChecksumComponents::default()
+            .track::<Health>()
+            .track_with::<Exclude>(|exclude| unordered_checksum(exclude.0.iter())),
+    ))
+    .add_system(exclude_system.before(checksum_system).in_schedule(GGRSSchedule));
+
+    let session = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(2)
+        .with_check_distance(2)
+        .add_player(PlayerType::Local, 0)
+        .unwrap()
+        .add_player(PlayerType::Local, 1)
+        .unwrap()
+        .start_synctest_session()
+        .unwrap();
+    app.insert_resource(Session::SyncTestSession(session));
+
+    let id = app.world.resource_mut::<RollbackIdProvider>().next_id();
+    app.world.spawn((Rollback::new(id), Health(500), Exclude::default()));
+
+    // A little over a frame each update, so the session advances exactly once per update
+    let step = Duration::from_secs(1) / FPS + Duration::from_micros(1);
+    let mut now = Instant::now();
+    app.world.resource_mut::<Time>().update_with_instant(now);
+
+    for _ in 0..FRAMES {
+        now += step;
+        app.world.resource_mut::<Time>().update_with_instant(now);
+        app.update();
+    }
+
+    assert_eq!(app.world.resource::<RollbackFrame>().0, FRAMES);
+    assert!(app.world.resource::<DesyncReport>().0.is_empty());
+}
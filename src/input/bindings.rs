@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use bevy::asset::FileAssetIo;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::HashMap;
+use leafwing_input_manager::prelude::{InputMap, SingleAxis};
+use serde::{Deserialize, Serialize};
+
+use super::{Action, SocdPolicy};
+
+pub const INPUT_CONFIG_PATHS: [&str; 2] = ["data/input/player1.input", "data/input/player2.input"];
+
+// How far a stick has to be pushed before it counts as a direction
+const AXIS_THRESHOLD: f32 = 0.5;
+
+// The sticks and triggers that can be bound when rebinding
+const REBIND_AXES: [GamepadAxisType; 6] = [
+    GamepadAxisType::LeftStickX,
+    GamepadAxisType::LeftStickY,
+    GamepadAxisType::LeftZ,
+    GamepadAxisType::RightStickX,
+    GamepadAxisType::RightStickY,
+    GamepadAxisType::RightZ,
+];
+
+// The order actions are asked for when rebinding
+const REBIND_ORDER: [Action; 10] = [
+    Action::A,
+    Action::B,
+    Action::C,
+    Action::J,
+    Action::K,
+    Action::L,
+    Action::Left,
+    Action::Right,
+    Action::Up,
+    Action::Down,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+    AxisPositive(GamepadAxisType),
+    AxisNegative(GamepadAxisType),
+}
+
+impl Binding {
+    fn same_device(&self, other: &Binding) -> bool {
+        matches!(
+            (self, other),
+            (Binding::Key(_), Binding::Key(_))
+                | (
+                    Binding::Button(_) | Binding::AxisPositive(_) | Binding::AxisNegative(_),
+                    Binding::Button(_) | Binding::AxisPositive(_) | Binding::AxisNegative(_)
+                )
+        )
+    }
+}
+
+/// Controls for one local player, loaded from a `.input` file
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TypeUuid)]
+#[uuid = "5b0c1a4e-8f3d-4c52-9a1e-6d2f7b3c9e41"]
+pub struct InputConfig {
+    /// Index of the gamepad this player uses. Without one, any gamepad works.
+    #[serde(default)]
+    pub gamepad: Option<usize>,
+    #[serde(default)]
+    pub socd: SocdPolicy,
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl InputConfig {
+    pub fn input_map(&self) -> InputMap<Action> {
+        let mut input_map = InputMap::default();
+
+        for (action, bindings) in self.bindings.iter() {
+            for binding in bindings {
+                match *binding {
+                    Binding::Key(key) => input_map.insert(key, *action),
+                    Binding::Button(button) => input_map.insert(button, *action),
+                    Binding::AxisPositive(axis) => {
+                        input_map.insert(SingleAxis::positive_only(axis, AXIS_THRESHOLD), *action)
+                    }
+                    Binding::AxisNegative(axis) => {
+                        input_map.insert(SingleAxis::negative_only(axis, -AXIS_THRESHOLD), *action)
+                    }
+                };
+            }
+        }
+
+        if let Some(gamepad) = self.gamepad {
+            input_map.set_gamepad(Gamepad::new(gamepad));
+        }
+
+        input_map
+    }
+
+    /// Replaces the bindings of the action that use the same kind of device, so a new
+    /// key doesn't unbind the gamepad and the other way around
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        bindings.retain(|existing| !existing.same_device(&binding));
+        bindings.push(binding);
+    }
+}
+
+/// Handles of the input configs, one for each local player in the order of `LocalPlayers`
+#[derive(Resource)]
+pub struct InputConfigHandles(pub Vec<Handle<InputConfig>>);
+
+/// Which local player an `InputMap` belongs to
+#[derive(Component, Debug, Clone, Copy)]
+pub struct InputSlot(pub usize);
+
+/// The local player being rebound, and the index of the action in `REBIND_ORDER` that's waiting for an input
+#[derive(Resource, Default, Debug)]
+pub struct Rebinding(pub Option<(usize, usize)>);
+
+pub fn load_input_configs(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let handles = INPUT_CONFIG_PATHS
+        .iter()
+        .map(|path| asset_server.load(*path))
+        .collect();
+
+    commands.insert_resource(InputConfigHandles(handles));
+}
+
+/// Where an input config gets saved, under the folder the asset plugin loads from.
+/// Without a file system to load assets from, there's nowhere to save it.
+fn config_path(asset_server: &AssetServer, handle: &Handle<InputConfig>) -> Option<PathBuf> {
+    let path = asset_server.get_handle_path(handle)?;
+    let asset_io = asset_server.asset_io().downcast_ref::<FileAssetIo>()?;
+    Some(asset_io.root_path().join(path.path()))
+}
+
+/// F1 and F2 start rebinding the first and second local player. Each action is then
+/// bound to the next key, gamepad button or stick direction pressed, and the config is
+/// saved once every action has been bound. Backspace cancels.
+#[allow(clippy::too_many_arguments)]
+pub fn rebind_system(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut pushed_axes: Local<HashMap<GamepadAxis, Binding>>,
+    mut rebinding: ResMut<Rebinding>,
+    handles: Res<InputConfigHandles>,
+    mut configs: ResMut<Assets<InputConfig>>,
+    asset_server: Res<AssetServer>,
+    mut query: Query<(&InputSlot, &mut InputMap<Action>)>,
+) {
+    // A stick only counts on the frame it's pushed past the threshold, not for as long as it's held there
+    let held_axes: HashMap<GamepadAxis, Binding> = gamepads
+        .iter()
+        .flat_map(|gamepad| REBIND_AXES.map(|axis_type| GamepadAxis::new(gamepad, axis_type)))
+        .filter_map(|axis| match axes.get(axis)? {
+            value if value > AXIS_THRESHOLD => Some((axis, Binding::AxisPositive(axis.axis_type))),
+            value if value < -AXIS_THRESHOLD => Some((axis, Binding::AxisNegative(axis.axis_type))),
+            _ => None,
+        })
+        .collect();
+    let pushed_axis = held_axes
+        .iter()
+        .find(|(axis, binding)| pushed_axes.get(*axis) != Some(*binding))
+        .map(|(_, binding)| *binding);
+    *pushed_axes = held_axes;
+
+    if keys.just_pressed(KeyCode::Back) {
+        rebinding.0 = None;
+        return;
+    }
+
+    for (key, slot) in [(KeyCode::F1, 0), (KeyCode::F2, 1)] {
+        if keys.just_pressed(key) && query.iter().any(|(input_slot, _)| input_slot.0 == slot) {
+            info!("Rebinding player {}: press a button for {:?}", slot + 1, REBIND_ORDER[0]);
+            rebinding.0 = Some((slot, 0));
+            return;
+        }
+    }
+
+    let Some((slot, index)) = rebinding.0 else {
+        return;
+    };
+
+    let binding = keys
+        .get_just_pressed()
+        .find(|key| !matches!(key, KeyCode::F1 | KeyCode::F2))
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Button(button.button_type))
+        })
+        .or(pushed_axis);
+
+    let Some(binding) = binding else {
+        return;
+    };
+
+    let Some(config) = configs.get_mut(&handles.0[slot]) else {
+        rebinding.0 = None;
+        return;
+    };
+
+    config.rebind(REBIND_ORDER[index], binding);
+
+    if index + 1 < REBIND_ORDER.len() {
+        info!("Press a button for {:?}", REBIND_ORDER[index + 1]);
+        rebinding.0 = Some((slot, index + 1));
+        return;
+    }
+
+    rebinding.0 = None;
+
+    for (input_slot, mut input_map) in query.iter_mut() {
+        if input_slot.0 == slot {
+            *input_map = config.input_map();
+        }
+    }
+
+    let Some(path) = config_path(&asset_server, &handles.0[slot]) else {
+        warn!("Couldn't save player {} bindings: assets aren't loaded from files", slot + 1);
+        return;
+    };
+
+    let saved = serde_json::to_string_pretty(config)
+        .map_err(|err| err.to_string())
+        .and_then(|json| std::fs::write(&path, json).map_err(|err| err.to_string()));
+
+    match saved {
+        Ok(()) => info!("Saved player {} bindings to {}", slot + 1, path.display()),
+        Err(err) => error!("Couldn't save player {} bindings: {}", slot + 1, err),
+    }
+}
+
+#[test]
+fn configs_survive_a_save_and_load() {
+    let mut config: InputConfig =
+        serde_json::from_str(include_str!("../../assets/data/input/player1.input")).unwrap();
+    config.rebind(Action::A, Binding::Key(KeyCode::Z));
+    config.rebind(Action::Up, Binding::AxisPositive(GamepadAxisType::LeftStickY));
+
+    // Saved the same way rebind_system does, and loaded the same way as the asset
+    let path = std::env::temp_dir().join("configs_survive_a_save_and_load.input");
+    std::fs::write(&path, serde_json::to_string_pretty(&config).unwrap()).unwrap();
+    let loaded: InputConfig = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.gamepad, config.gamepad);
+    assert_eq!(loaded.socd, config.socd);
+    assert_eq!(loaded.bindings, config.bindings);
+    assert!(loaded.bindings[&Action::A].contains(&Binding::Key(KeyCode::Z)));
+    assert!(!loaded.bindings[&Action::A].contains(&Binding::Key(KeyCode::U)));
+    assert!(loaded.bindings[&Action::A].contains(&Binding::Button(GamepadButtonType::West)));
+}
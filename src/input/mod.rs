@@ -1,7 +1,11 @@
+pub mod bindings;
+pub mod notation;
+
 use bevy::prelude::*;
 use bevy::reflect::FromReflect;
 use bytemuck::{Pod, Zeroable};
 use ggrs::PlayerHandle;
+use bevy::utils::{HashMap, HashSet};
 use leafwing_input_manager::prelude::ActionState;
 use leafwing_input_manager::Actionlike;
 use packed_struct::prelude::{PackedStruct, PrimitiveEnum_u8};
@@ -12,9 +16,16 @@ use serde::{de, Deserialize, Serialize};
 use crate::fighter::state::{Facing, Direction, Frame};
 use crate::fighter::systems::InputBuffer;
 use crate::util::Buffer;
+use crate::Player;
+use bindings::{InputSlot, Rebinding};
 
 pub const BUFFER_SIZE: usize = 60;
 pub const BUFFER_TIME: Frame = 0;
+// Default window of commands with more than one step, e.g. motion inputs
+pub const MOTION_WINDOW: Frame = 18;
+pub const CHARGE_FRAMES: Frame = 45;
+// Frames a charge can be let go of without losing it
+pub const CHARGE_LENIENCY: Frame = 3;
 
 
 pub const LEFT_HELD: u32 = 2048;
@@ -23,13 +34,17 @@ pub const LEFT: u32 = 2560;
 pub const RIGHT_HELD: u32 = 1024;
 pub const RIGHT: u32 = 1536;
 
+/// GGRS handles of the players whose inputs are read on this machine
+#[derive(Resource, Debug, Clone)]
+pub struct LocalPlayers(pub Vec<PlayerHandle>);
+
 
 
 #[repr(C)]
 #[derive(Default, Debug, Copy, Clone, PartialEq, Pod, Zeroable, Reflect, FromReflect)]
 pub struct Input(pub u32);
 
-#[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug)]
+#[derive(Actionlike, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug, Serialize, Deserialize)]
 pub enum Action {
     A,
     B,
@@ -43,20 +58,67 @@ pub enum Action {
     Down,
 }
 
+/// How a direction and its opposite being held at the same time (SOCD) is resolved
+#[derive(PrimitiveEnum_u8, Component, Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum SocdPolicy {
+    // Left + right and up + down are both neutral
+    #[default]
+    Neutral = 0,
+    // Whichever direction was pressed last
+    LastWins = 1,
+    // Left + right is neutral, up + down is up
+    UpPriority = 2,
+}
+
+impl SocdPolicy {
+    /// Resolves both directions of an axis being held. Which ones were held on the previous
+    /// input tells which was pressed last, since leafwing's `just_pressed` is per render frame.
+    fn resolve(
+        &self,
+        vertical: bool,
+        previous: DirectionalInput,
+        pos_held_before: bool,
+        neg_held_before: bool,
+    ) -> DirectionalInput {
+        match self {
+            SocdPolicy::Neutral => DirectionalInput::None,
+            SocdPolicy::UpPriority if vertical => DirectionalInput::Positive,
+            SocdPolicy::UpPriority => DirectionalInput::None,
+            SocdPolicy::LastWins => match (pos_held_before, neg_held_before) {
+                (true, false) => DirectionalInput::Negative,
+                (false, true) => DirectionalInput::Positive,
+                _ => previous,
+            },
+        }
+    }
+}
+
 pub fn input(
     handle: In<PlayerHandle>,
-    mut input_query: Query<(&ActionState<Action>, &Facing)>,
-    mut local: Local<(StateInput, StateInput)>
+    input_query: Query<(&ActionState<Action>, &Facing, &Player, Option<&SocdPolicy>, Option<&InputSlot>)>,
+    rebinding: Option<Res<Rebinding>>,
+    mut local: Local<HashMap<PlayerHandle, (StateInput, HashSet<Action>)>>
 ) -> Input 
 {
-    if handle.0 == 1 {
-        return Input(StateInput::default().into());
+    let player_action_state = input_query
+        .iter()
+        .find(|(_, _, player, _, _)| player.0 as usize == handle.0 + 1);
+
+    // The keys pressed for a rebind aren't meant for the fighter, so it gets no input until it's done.
+    // Forgetting the previous input makes anything still held afterwards a fresh press.
+    let rebinding_slot = rebinding.and_then(|rebinding| rebinding.0).map(|(slot, _)| slot);
+    if let Some((_, _, _, _, Some(slot))) = player_action_state {
+        if rebinding_slot == Some(slot.0) {
+            local.remove(&handle.0);
+            return Input(StateInput::default().into());
+        }
     }
 
-    
-
-    if let Ok((action_state, facing)) = input_query.get_single_mut() {
-        let previous = &local.0;
+    if let Some((action_state, facing, _, socd, _)) = player_action_state {
+        let socd = socd.copied().unwrap_or_default();
+        // Each handle compares against its own previous input, otherwise presses turn into holds for the other player.
+        // The directions held before SOCD cleaning are kept with it, so LastWins can tell which was pressed last.
+        let (previous, held_before) = local.entry(handle.0).or_default().clone();
 
         let button_check = |action: Action| -> ButtonPress {
             let prev = previous.get_button_from_action(action);
@@ -81,29 +143,23 @@ pub fn input(
         };
 
         let directional_check = |pos: Action, neg: Action| -> (DirectionalInput, bool) {
-            let prev = previous.get_directional_from_button(pos);
+            let (prev, _) = previous.get_directional_from_button(pos);
             let check_pos = action_state.pressed(pos);
             let check_neg = action_state.pressed(neg);
 
-
-
-            if check_pos {
-                match prev {
-                    (DirectionalInput::Positive, _) => (DirectionalInput::Positive, false),
-                    _ => (DirectionalInput::Positive, true)
-                }
-            }
-            else if check_neg {
-                match prev {
-                    (DirectionalInput::Negative, _) => (DirectionalInput::Negative, false),
-                    _ => (DirectionalInput::Negative, true)
-                }
-            }
-            else {
-                (DirectionalInput::None, false)
-            }
-
-
+            let direction = match (check_pos, check_neg) {
+                (true, false) => DirectionalInput::Positive,
+                (false, true) => DirectionalInput::Negative,
+                (false, false) => DirectionalInput::None,
+                (true, true) => socd.resolve(
+                    pos == Action::Up,
+                    prev,
+                    held_before.contains(&pos),
+                    held_before.contains(&neg),
+                ),
+            };
+
+            (direction, direction != DirectionalInput::None && direction != prev)
         };
 
         let lp = button_check(Action::A);
@@ -124,9 +180,17 @@ pub fn input(
         //     }
         // }
 
-        let inp = StateInput::new(lp, mp, hp, lk, mk, hk, x, just_pressed_x, y, just_pressed_y);
+        // The policy goes over the network with the input, so every peer can see how it was cleaned
+        let inp = StateInput {
+            socd,
+            ..StateInput::new(lp, mp, hp, lk, mk, hk, x, just_pressed_x, y, just_pressed_y)
+        };
 
-        local.0 = inp.clone();
+        let held = [Action::Left, Action::Right, Action::Up, Action::Down]
+            .into_iter()
+            .filter(|action| action_state.pressed(*action))
+            .collect();
+        local.insert(handle.0, (inp.clone(), held));
 
         let inp: u32 = inp.into();
         //buffer.0.insert(inp);
@@ -138,11 +202,74 @@ pub fn input(
 }
 
 
-#[derive(Debug, Serialize, Deserialize, FromReflect, Reflect, Clone)]
+#[derive(Debug, Serialize, Deserialize, FromReflect, Reflect, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum MatchExpression {
     Button(String, ButtonPress),
     Directional(String, DirectionalInput, bool),
+    // Any of these numpad directions, whether or not it was just pressed, e.g. `[1, 2, 3]` for any down
+    Direction(Vec<u8>),
+    // Any of these directions held for `frames` frames up to this one, e.g. `{"charge": [1, 4, 7], "frames": 45}`
+    Charge {
+        charge: Vec<u8>,
+        #[serde(default = "MatchExpression::charge_frames_default")]
+        frames: Frame,
+        #[serde(default = "MatchExpression::charge_leniency_default")]
+        leniency: Frame,
+    },
+}
+
+impl MatchExpression {
+    fn charge_frames_default() -> Frame {
+        CHARGE_FRAMES
+    }
+
+    fn charge_leniency_default() -> Frame {
+        CHARGE_LENIENCY
+    }
+
+    fn charged(directions: &[u8], frames: Frame, leniency: Frame, inputs: impl Iterator<Item = StateInput>) -> bool {
+        let mut held = 0;
+        let mut missed = 0;
+
+        for input in inputs {
+            if directions.contains(&input.numpad()) {
+                held += 1;
+                if held >= frames {
+                    return true;
+                }
+            }
+            else {
+                missed += 1;
+                if missed > leniency {
+                    return false;
+                }
+            }
+        }
+
+        false
+    }
+
+    // Whether the expressions only match a diagonal direction, which lenient commands can skip
+    fn is_diagonal(command: &[MatchExpression]) -> bool {
+        let mut x = DirectionalInput::None;
+        let mut y = DirectionalInput::None;
+
+        for expression in command {
+            match expression {
+                MatchExpression::Direction(directions) => {
+                    return command.len() == 1
+                        && !directions.is_empty()
+                        && directions.iter().all(|direction| matches!(direction, 1 | 3 | 7 | 9));
+                }
+                MatchExpression::Directional(axis, value, _) if axis == "x" => x = *value,
+                MatchExpression::Directional(axis, value, _) if axis == "y" => y = *value,
+                _ => return false,
+            }
+        }
+
+        x != DirectionalInput::None && y != DirectionalInput::None
+    }
 }
 
 impl Default for MatchExpression {
@@ -151,33 +278,160 @@ impl Default for MatchExpression {
     }
 }
 
+/// Can be written as notation (see `notation`), e.g. `"236A"`, or as an object with either
+/// a `notation` or a `list` of match expressions plus a `window` and `bufferTime`
 #[derive(Debug, Serialize, Deserialize, Default, FromReflect, Reflect, Clone)]
+#[serde(try_from = "SerializedCommandInput", into = "SerializedCommandInput")]
 pub struct CommandInput {
     list: Vec<Vec<MatchExpression>>,
-    #[serde(default = "CommandInput::window_default")]
     window: Frame,
-    #[serde(default = "CommandInput::buffer_time_default", alias = "bufferTime")]
-    buffer_time: Frame
+    buffer_time: Frame,
+    lenient: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SerializedCommandInput {
+    Notation(String),
+    Full {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        notation: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        list: Option<Vec<Vec<MatchExpression>>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        window: Option<Frame>,
+        #[serde(default = "CommandInput::buffer_time_default", alias = "bufferTime")]
+        buffer_time: Frame,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        lenient: bool,
+    },
+}
+
+impl TryFrom<SerializedCommandInput> for CommandInput {
+    type Error = String;
+
+    fn try_from(value: SerializedCommandInput) -> Result<Self, Self::Error> {
+        let (list, window, buffer_time, lenient) = match value {
+            SerializedCommandInput::Notation(notation) => (notation::parse(&notation)?, None, BUFFER_TIME, false),
+            SerializedCommandInput::Full { notation, list, window, buffer_time, lenient } => {
+                let list = match (notation, list) {
+                    (Some(notation), None) => notation::parse(&notation)?,
+                    (None, Some(list)) => {
+                        // Notation checks its own charges, a list has to be checked here
+                        let too_long = list.iter().flatten().find_map(|expression| match expression {
+                            MatchExpression::Charge { frames, .. } if *frames as usize > BUFFER_SIZE => Some(*frames),
+                            _ => None,
+                        });
+                        if let Some(frames) = too_long {
+                            return Err(format!("Charge of {} frames is longer than the {} frame buffer", frames, BUFFER_SIZE));
+                        }
+
+                        list
+                    }
+                    _ => return Err("A command needs either a notation or a list".to_string()),
+                };
+
+                (list, window, buffer_time, lenient)
+            }
+        };
+
+        Ok(CommandInput {
+            window: window.unwrap_or_else(|| CommandInput::window_default(list.len())),
+            list,
+            buffer_time,
+            lenient,
+        })
+    }
+}
+
+impl From<CommandInput> for SerializedCommandInput {
+    fn from(value: CommandInput) -> Self {
+        let window = (value.window != CommandInput::window_default(value.list.len())).then_some(value.window);
+
+        match value.notation() {
+            Some(notation) if window.is_none() && value.buffer_time == BUFFER_TIME && !value.lenient => {
+                SerializedCommandInput::Notation(notation)
+            }
+            Some(notation) => SerializedCommandInput::Full {
+                notation: Some(notation),
+                list: None,
+                window,
+                buffer_time: value.buffer_time,
+                lenient: value.lenient,
+            },
+            None => SerializedCommandInput::Full {
+                notation: None,
+                list: Some(value.list),
+                window,
+                buffer_time: value.buffer_time,
+                lenient: value.lenient,
+            },
+        }
+    }
 }
 
 impl CommandInput {
+    pub fn from_notation(notation: &str) -> Result<Self, String> {
+        SerializedCommandInput::Notation(notation.to_string()).try_into()
+    }
+
     fn buffer_time_default() -> Frame {
         BUFFER_TIME
     }
 
-    fn window_default() -> Frame {
-        1
+    fn window_default(steps: usize) -> Frame {
+        if steps > 1 {
+            MOTION_WINDOW
+        }
+        else {
+            1
+        }
+    }
+
+    /// The command written as notation, if it can be
+    pub fn notation(&self) -> Option<String> {
+        notation::to_notation(&self.list)
+    }
+
+    // The input as if the fighter was facing right
+    pub fn relative_input(input: &u32, facing: Direction) -> StateInput {
+        let mut input: StateInput = input.into();
+
+        if facing == Direction::Left {
+            match input.x {
+                DirectionalInput::None => {},
+                DirectionalInput::Positive => {
+                    input.x = DirectionalInput::Negative;
+                },
+                DirectionalInput::Negative =>{
+                    input.x = DirectionalInput::Positive;
+                },
+            }
+        }
+
+        input
     }
 
     pub fn compare(&self, input: &Buffer, facing: Direction) -> bool {
-        let mut input_iter = input.iter();
+        self.compare_recent(input, facing, input.vec.len())
+    }
+
+    /// Like `compare`, but only sees the newest `frames` inputs in the buffer
+    pub fn compare_recent(&self, input: &Buffer, facing: Direction, frames: usize) -> bool {
+        let mut input_iter = input.iter().take(frames);
         let mut index = 0;
 
         let mut buffer_time = 0;
+        // Set when a lenient command skipped a diagonal, and the frame also matched the step after it
+        let mut skipped = false;
 
         // iterate over each match expression
-        for command in &self.list {
-            
+        for (i, command) in self.list.iter().enumerate() {
+            if skipped {
+                skipped = false;
+                continue;
+            }
+
             loop {
 
                 index += 1;
@@ -186,19 +440,7 @@ impl CommandInput {
                 }
 
                 if let Some(next) = input_iter.next() {
-                    let mut next: StateInput = next.into();
-
-                    if facing == Direction::Left {
-                        match next.x {
-                            DirectionalInput::None => {},
-                            DirectionalInput::Positive => {
-                                next.x = DirectionalInput::Negative;
-                            },
-                            DirectionalInput::Negative =>{
-                                next.x = DirectionalInput::Positive;
-                            },
-                        }
-                    }
+                    let next = Self::relative_input(next, facing);
 
 
                     //let mut command_iter = command.iter();
@@ -218,7 +460,35 @@ impl CommandInput {
                         }
                     }
 
+                    // Charges also have to have been held on the frames before this one
                     if same {
+                        same = command.iter().all(|expression| match expression {
+                            MatchExpression::Charge { charge, frames, leniency } => MatchExpression::charged(
+                                charge,
+                                *frames,
+                                *leniency,
+                                std::iter::once(next.clone())
+                                    .chain(input_iter.clone().map(|input| Self::relative_input(input, facing))),
+                            ),
+                            _ => true,
+                        });
+                    }
+
+                    if same {
+                        break;
+                    }
+
+                    // e.g. 2 straight into 6 still counts as 236
+                    if self.lenient
+                        && i > 0
+                        && MatchExpression::is_diagonal(command)
+                        && self.list.get(i + 1).map_or(false, |following| {
+                            following
+                                .iter()
+                                .all(|expression| next.compare_command(expression.clone()))
+                        })
+                    {
+                        skipped = true;
                         break;
                     }
                 } else {
@@ -359,8 +629,11 @@ pub struct StateInput {
     #[packed_field(bits = "17")]
     pub just_pressed_y: bool,
     #[serde(default)]
-    #[packed_field(bits = "18..=31")]
-    _reserved: ReservedZero<Bits<14>>,
+    #[packed_field(bits = "18..=19", ty = "enum")]
+    pub socd: SocdPolicy,
+    #[serde(default)]
+    #[packed_field(bits = "20..=31")]
+    _reserved: ReservedZero<Bits<12>>,
 }
 
 impl StateInput {
@@ -388,6 +661,7 @@ impl StateInput {
             just_pressed_x,
             y,
             just_pressed_y,
+            socd: SocdPolicy::default(),
             _reserved: ReservedZero::default(),
         }
     }
@@ -438,9 +712,28 @@ impl StateInput {
 
                 i.0 == direction && (i.1 == just_pressed)
             }
+            MatchExpression::Direction(directions) => directions.contains(&self.numpad()),
+            // How long it was held is checked against the buffer in `CommandInput::compare`
+            MatchExpression::Charge { charge, .. } => charge.contains(&self.numpad()),
         }
     }
 
+    /// The direction in numpad notation, for a fighter facing right
+    pub fn numpad(&self) -> u8 {
+        let column = match self.x {
+            DirectionalInput::Negative => 1,
+            DirectionalInput::None => 2,
+            DirectionalInput::Positive => 3,
+        };
+        let row = match self.y {
+            DirectionalInput::Negative => 0,
+            DirectionalInput::None => 3,
+            DirectionalInput::Positive => 6,
+        };
+
+        row + column
+    }
+
     pub fn lp(just_pressed: bool) -> Self {
         let lp = if just_pressed {
             ButtonPress::Press
@@ -618,3 +911,164 @@ impl From<[u8; 4]> for StateInput {
 }
 
 
+
+#[test]
+fn lenient_motion_skips_diagonal() {
+    let strict: CommandInput = serde_json::from_str(r#""236A""#).unwrap();
+    let lenient: CommandInput = serde_json::from_str(r#"{"notation": "236A", "lenient": true}"#).unwrap();
+
+    // 2, then straight to 6 and A without passing through 3
+    let mut buffer = Buffer::with_capacity(BUFFER_SIZE);
+    buffer.insert(StateInput::down_bits(true));
+    buffer.insert(StateInput::down_bits(false));
+    buffer.insert(StateInput::right_bits(true));
+    buffer.insert(
+        StateInput {
+            a: ButtonPress::Press,
+            ..StateInput::right(false)
+        }
+        .into(),
+    );
+
+    assert!(!strict.compare(&buffer, Direction::Right));
+    assert!(lenient.compare(&buffer, Direction::Right));
+}
+
+#[test]
+fn charge_needs_full_hold() {
+    let command: CommandInput = serde_json::from_str(r#""{4}6A""#).unwrap();
+
+    let charge_then_6a = |frames| {
+        let mut buffer = Buffer::with_capacity(BUFFER_SIZE);
+        buffer.insert(StateInput::left_bits(true));
+        for _ in 1..frames {
+            buffer.insert(StateInput::left_bits(false));
+        }
+        buffer.insert(StateInput::right_bits(true));
+        buffer.insert(
+            StateInput {
+                a: ButtonPress::Press,
+                ..StateInput::right(false)
+            }
+            .into(),
+        );
+        buffer
+    };
+
+    assert!(serde_json::from_str::<CommandInput>(r#"{"list": [[{"charge": [4], "frames": 61}]]}"#).is_err());
+
+    assert!(command.compare(&charge_then_6a(CHARGE_FRAMES), Direction::Right));
+    assert!(!command.compare(&charge_then_6a(30), Direction::Right));
+    // Facing left, back is 6 in the buffer
+    assert!(!command.compare(&charge_then_6a(CHARGE_FRAMES), Direction::Left));
+}
+
+#[test]
+fn socd_policies_resolve_both_directions() {
+    use DirectionalInput::*;
+
+    for (pos_before, neg_before) in [(false, false), (true, false), (false, true), (true, true)] {
+        assert_eq!(SocdPolicy::Neutral.resolve(false, Positive, pos_before, neg_before), None);
+        assert_eq!(SocdPolicy::Neutral.resolve(true, Negative, pos_before, neg_before), None);
+        assert_eq!(SocdPolicy::UpPriority.resolve(false, Positive, pos_before, neg_before), None);
+        assert_eq!(SocdPolicy::UpPriority.resolve(true, Negative, pos_before, neg_before), Positive);
+    }
+
+    // The direction that wasn't held before is the one pressed last
+    assert_eq!(SocdPolicy::LastWins.resolve(false, Positive, true, false), Negative);
+    assert_eq!(SocdPolicy::LastWins.resolve(false, Negative, false, true), Positive);
+    // Both still held keeps the winner, both pressed at once stays neutral
+    assert_eq!(SocdPolicy::LastWins.resolve(false, Negative, true, true), Negative);
+    assert_eq!(SocdPolicy::LastWins.resolve(false, None, false, false), None);
+}
+
+#[test]
+fn last_wins_follows_the_previous_input() {
+    let mut world = World::new();
+    let player = world
+        .spawn((
+            ActionState::<Action>::default(),
+            Facing(Direction::Right),
+            Player(1),
+            SocdPolicy::LastWins,
+        ))
+        .id();
+
+    let mut system = IntoSystem::into_system(input);
+    system.initialize(&mut world);
+
+    // Only the held actions change between inputs, so leafwing's just_pressed never decides anything
+    let mut step = |world: &mut World, held: &[Action]| -> DirectionalInput {
+        let mut action_state = world.get_mut::<ActionState<Action>>(player).unwrap();
+        for action in [Action::Left, Action::Right] {
+            if held.contains(&action) {
+                action_state.press(action);
+            } else {
+                action_state.release(action);
+            }
+        }
+        StateInput::from(system.run(0, world).0).x
+    };
+
+    assert_eq!(step(&mut world, &[Action::Right]), DirectionalInput::Positive);
+    assert_eq!(step(&mut world, &[Action::Right, Action::Left]), DirectionalInput::Negative);
+    assert_eq!(step(&mut world, &[Action::Right, Action::Left]), DirectionalInput::Negative);
+    assert_eq!(step(&mut world, &[Action::Left]), DirectionalInput::Negative);
+    assert_eq!(step(&mut world, &[Action::Left, Action::Right]), DirectionalInput::Positive);
+    assert_eq!(step(&mut world, &[Action::Right]), DirectionalInput::Positive);
+}
+
+#[test]
+fn rebinding_player_gets_no_input() {
+    let mut world = World::new();
+    world.insert_resource(Rebinding(Some((0, 3))));
+    let mut action_state = ActionState::<Action>::default();
+    action_state.press(Action::Right);
+    action_state.press(Action::A);
+    world.spawn((action_state, Facing(Direction::Right), Player(1), InputSlot(0)));
+    world.spawn((ActionState::<Action>::default(), Facing(Direction::Left), Player(2), InputSlot(1)));
+
+    let mut system = IntoSystem::into_system(input);
+    system.initialize(&mut world);
+
+    assert_eq!(StateInput::from(system.run(0, &mut world).0), StateInput::default());
+
+    // Whatever is still held once the rebind is done gets pressed on the next input
+    world.resource_mut::<Rebinding>().0 = None;
+    let inp = StateInput::from(system.run(0, &mut world).0);
+    assert_eq!((inp.x, inp.just_pressed_x), (DirectionalInput::Positive, true));
+    assert_eq!(inp.a, ButtonPress::Press);
+}
+
+#[test]
+fn each_handle_reads_its_own_controls() {
+    let action_state = |action: Action| {
+        let mut action_state = ActionState::<Action>::default();
+        action_state.press(action);
+        action_state
+    };
+
+    // Two local players, each on their own slot
+    let mut world = World::new();
+    world.spawn((action_state(Action::A), Facing(Direction::Right), Player(1), InputSlot(0)));
+    world.spawn((action_state(Action::B), Facing(Direction::Left), Player(2), InputSlot(1)));
+
+    let mut system = IntoSystem::into_system(input);
+    system.initialize(&mut world);
+
+    let p1 = StateInput::from(system.run(0, &mut world).0);
+    let p2 = StateInput::from(system.run(1, &mut world).0);
+    assert_eq!((p1.a, p1.b), (ButtonPress::Press, ButtonPress::None));
+    assert_eq!((p2.a, p2.b), (ButtonPress::None, ButtonPress::Press));
+
+    // Online as the second handle, the first slot's controls belong to player 2 and player 1 is remote
+    let mut world = World::new();
+    world.spawn((Facing(Direction::Right), Player(1)));
+    world.spawn((action_state(Action::A), Facing(Direction::Left), Player(2), InputSlot(0)));
+
+    let mut system = IntoSystem::into_system(input);
+    system.initialize(&mut world);
+
+    assert_eq!(StateInput::from(system.run(0, &mut world).0), StateInput::default());
+    assert_eq!(StateInput::from(system.run(1, &mut world).0).a, ButtonPress::Press);
+}
@@ -0,0 +1,417 @@
+//! Fighting game notation for command inputs, e.g. `236A`, `{4}6B`, `623+C` or `~A`.
+//!
+//! Directions are numpad digits for a fighter facing right. Buttons are `A`, `B`, `C`, `J`, `K` and `L`,
+//! `~` in front of a button means it was released, and brackets mean a button or direction is held.
+//! A direction in braces is a charge, e.g. `{4}` for any back held for `CHARGE_FRAMES`, or `{2:30}`
+//! for any down held for 30 frames. A charge can't be longer than the `BUFFER_SIZE` frames kept.
+//! Parentheses match any of the directions in them, pressed or held, e.g. `(123)` for any down.
+//! A direction written right before a button (or joined to it with `+`) is the direction held while
+//! pressing the button. Whitespace keeps two steps from being joined, e.g. `66 A`.
+
+use super::{ButtonPress, DirectionalInput, MatchExpression, StateInput, BUFFER_SIZE, CHARGE_FRAMES, CHARGE_LENIENCY};
+use crate::fighter::state::Frame;
+
+const BUTTONS: [(char, &str); 6] = [
+    ('A', "a"),
+    ('B', "b"),
+    ('C', "c"),
+    ('J', "lk"),
+    ('K', "mk"),
+    ('L', "hk"),
+];
+
+#[derive(Debug, Default, Clone)]
+struct Step {
+    direction: Option<u8>,
+    // The direction may already be held instead of being pressed on this step
+    held: bool,
+    // Direction and frames of a charge
+    charge: Option<(u8, Frame)>,
+    any_of: Vec<u8>,
+    buttons: Vec<(char, ButtonPress)>,
+}
+
+fn axes(direction: u8) -> (DirectionalInput, DirectionalInput) {
+    let x = match direction % 3 {
+        0 => DirectionalInput::Positive,
+        1 => DirectionalInput::Negative,
+        _ => DirectionalInput::None,
+    };
+    let y = match direction {
+        7..=9 => DirectionalInput::Positive,
+        1..=3 => DirectionalInput::Negative,
+        _ => DirectionalInput::None,
+    };
+
+    (x, y)
+}
+
+fn direction_from_axes(x: DirectionalInput, y: DirectionalInput) -> u8 {
+    StateInput {
+        x,
+        y,
+        ..Default::default()
+    }
+    .numpad()
+}
+
+// Every direction that holds the charge of a direction, e.g. 1, 4 and 7 for 4
+fn charge_directions(direction: u8) -> Vec<u8> {
+    let (x, y) = axes(direction);
+
+    (1..=9)
+        .filter(|other| {
+            let (other_x, other_y) = axes(*other);
+            (x == DirectionalInput::None || x == other_x) && (y == DirectionalInput::None || y == other_y)
+        })
+        .collect()
+}
+
+fn button_name(button: char) -> Option<&'static str> {
+    BUTTONS
+        .iter()
+        .find(|(c, _)| *c == button)
+        .map(|(_, name)| *name)
+}
+
+fn button_char(name: &str) -> Option<char> {
+    BUTTONS.iter().find(|(_, n)| *n == name).map(|(c, _)| *c)
+}
+
+/// Compiles notation into the match expressions of `CommandInput`, most recent step first
+pub fn parse(notation: &str) -> Result<Vec<Vec<MatchExpression>>, String> {
+    Ok(compile(&parse_steps(notation)?))
+}
+
+/// Writes match expressions back as notation, if they can be written that way
+pub fn to_notation(list: &[Vec<MatchExpression>]) -> Option<String> {
+    let mut steps = Vec::new();
+
+    for expressions in list.iter().rev() {
+        let mut step = Step::default();
+        let mut x = None;
+        let mut y = None;
+
+        for expression in expressions {
+            match expression {
+                MatchExpression::Button(name, press) => {
+                    step.buttons.push((button_char(name)?, *press));
+                }
+                MatchExpression::Directional(axis, value, _) if axis == "x" && x.is_none() => {
+                    x = Some(*value)
+                }
+                MatchExpression::Directional(axis, value, _) if axis == "y" && y.is_none() => {
+                    y = Some(*value)
+                }
+                MatchExpression::Direction(directions)
+                    if step.any_of.is_empty() && !directions.is_empty() =>
+                {
+                    step.any_of = directions.clone()
+                }
+                MatchExpression::Charge { charge, frames, leniency }
+                    if step.charge.is_none() && *leniency == CHARGE_LENIENCY =>
+                {
+                    let mut charge = charge.clone();
+                    charge.sort_unstable();
+                    charge.dedup();
+
+                    let direction = (1..=9).find(|direction| charge_directions(*direction) == charge)?;
+                    step.charge = Some((direction, *frames));
+                }
+                _ => return None,
+            }
+        }
+
+        step.direction = match (x, y) {
+            (Some(_), Some(_)) if !step.any_of.is_empty() => return None,
+            (Some(x), Some(y)) => Some(direction_from_axes(x, y)),
+            (None, None) => None,
+            _ => return None,
+        };
+
+        steps.push(step);
+    }
+
+    // Directions that were written as pressed but have to be held are bracketed
+    let compiled = compile(&steps);
+    for (step, (compiled, expressions)) in steps
+        .iter_mut()
+        .zip(compiled.iter().rev().zip(list.iter().rev()))
+    {
+        if step.buttons.is_empty() && step.direction.is_some() && !same_step(compiled, expressions) {
+            step.held = true;
+        }
+    }
+
+    let compiled = compile(&steps);
+    if compiled.len() != list.len()
+        || !compiled
+            .iter()
+            .zip(list.iter())
+            .all(|(compiled, expressions)| same_step(compiled, expressions))
+    {
+        return None;
+    }
+
+    let mut notation = String::new();
+    for step in steps.iter() {
+        match (step.direction, step.charge) {
+            (Some(direction), _) if step.held => notation.push_str(&format!("[{}]", direction)),
+            (Some(direction), _) => notation.push_str(&direction.to_string()),
+            (None, Some((direction, CHARGE_FRAMES))) => notation.push_str(&format!("{{{}}}", direction)),
+            (None, Some((direction, frames))) => notation.push_str(&format!("{{{}:{}}}", direction, frames)),
+            (None, None) if !step.any_of.is_empty() => {}
+            (None, None) if !notation.is_empty() => notation.push(' '),
+            (None, None) => {}
+        }
+
+        if !step.any_of.is_empty() {
+            notation.push('(');
+            for direction in step.any_of.iter() {
+                notation.push_str(&direction.to_string());
+            }
+            notation.push(')');
+        }
+
+        for (button, press) in step.buttons.iter() {
+            match press {
+                ButtonPress::Press => notation.push(*button),
+                ButtonPress::Release => notation.push_str(&format!("~{}", button)),
+                ButtonPress::Hold => notation.push_str(&format!("[{}]", button)),
+                _ => return None,
+            }
+        }
+    }
+
+    Some(notation)
+}
+
+fn same_step(a: &[MatchExpression], b: &[MatchExpression]) -> bool {
+    a.len() == b.len() && a.iter().all(|expression| b.contains(expression))
+}
+
+fn parse_steps(notation: &str) -> Result<Vec<Step>, String> {
+    let mut steps: Vec<Step> = Vec::new();
+    // Whether the next button is pressed on the same step as the last one
+    let mut joined = false;
+    let mut chars = notation.chars();
+
+    let push_button = |steps: &mut Vec<Step>, joined: bool, button: char, press: ButtonPress| {
+        if button_name(button).is_none() {
+            return Err(format!("Unknown button '{}' in \"{}\"", button, notation));
+        }
+
+        match steps.last_mut() {
+            Some(step) if joined => step.buttons.push((button, press)),
+            _ => steps.push(Step {
+                buttons: vec![(button, press)],
+                ..Default::default()
+            }),
+        }
+
+        Ok(())
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '1'..='9' => steps.push(Step {
+                direction: Some(c as u8 - b'0'),
+                ..Default::default()
+            }),
+            '(' => {
+                let mut any_of = Vec::new();
+                loop {
+                    match chars.next() {
+                        Some(direction @ '1'..='9') => any_of.push(direction as u8 - b'0'),
+                        Some(')') if !any_of.is_empty() => break,
+                        _ => return Err(format!("Unclosed '(' in \"{}\"", notation)),
+                    }
+                }
+
+                steps.push(Step {
+                    any_of,
+                    ..Default::default()
+                });
+            }
+            '+' if !steps.is_empty() => {}
+            '~' => {
+                let button = chars.next().unwrap_or_default();
+                push_button(&mut steps, joined, button, ButtonPress::Release)?;
+            }
+            '[' => match (chars.next(), chars.next()) {
+                (Some(direction @ '1'..='9'), Some(']')) => steps.push(Step {
+                    direction: Some(direction as u8 - b'0'),
+                    held: true,
+                    ..Default::default()
+                }),
+                (Some(button), Some(']')) => {
+                    push_button(&mut steps, joined, button, ButtonPress::Hold)?
+                }
+                _ => return Err(format!("Unclosed '[' in \"{}\"", notation)),
+            },
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| format!("Unclosed '{{' in \"{}\"", notation))?;
+                let mut inner_chars = rest[..end].chars();
+                chars = rest[end + 1..].chars();
+
+                let (direction, frames) = match (inner_chars.next(), inner_chars.next()) {
+                    (Some(direction @ '1'..='9'), None) => (direction, CHARGE_FRAMES),
+                    (Some(direction @ '1'..='9'), Some(':')) => {
+                        let frames = inner_chars
+                            .as_str()
+                            .parse()
+                            .map_err(|_| format!("Bad charge frames in \"{}\"", notation))?;
+                        (direction, frames)
+                    }
+                    _ => return Err(format!("Bad charge in \"{}\"", notation)),
+                };
+
+                // Only BUFFER_SIZE frames of input are kept, so a longer charge could never be met
+                if frames as usize > BUFFER_SIZE {
+                    return Err(format!(
+                        "Charge of {} frames in \"{}\" is longer than the {} frame buffer",
+                        frames, notation, BUFFER_SIZE
+                    ));
+                }
+
+                steps.push(Step {
+                    charge: Some((direction as u8 - b'0', frames)),
+                    ..Default::default()
+                });
+            }
+            c if c.is_whitespace() => {
+                joined = false;
+                continue;
+            }
+            c => push_button(&mut steps, joined, c, ButtonPress::Press)?,
+        }
+
+        joined = true;
+    }
+
+    if steps.is_empty() {
+        return Err(format!("\"{}\" has no inputs", notation));
+    }
+
+    Ok(steps)
+}
+
+fn compile(steps: &[Step]) -> Vec<Vec<MatchExpression>> {
+    let mut list = Vec::new();
+    let mut previous: Option<u8> = None;
+
+    for (i, step) in steps.iter().enumerate() {
+        let mut expressions: Vec<MatchExpression> = step
+            .buttons
+            .iter()
+            .map(|(button, press)| {
+                MatchExpression::Button(button_name(*button).unwrap_or_default().to_string(), *press)
+            })
+            .collect();
+
+        if let Some(direction) = step.direction {
+            let (x, y) = axes(direction);
+
+            // The axes that were pressed on this step. Pressing the same direction twice means
+            // it was let go in between, and the first direction of a motion may already be held.
+            let pressed_from = if step.held || !step.buttons.is_empty() {
+                direction
+            } else {
+                match previous {
+                    Some(previous) if previous == direction => 5,
+                    Some(previous) => previous,
+                    None if next_direction(steps, i) == Some(direction) => 5,
+                    None => direction,
+                }
+            };
+            let (from_x, from_y) = axes(pressed_from);
+
+            expressions.push(MatchExpression::Directional(
+                "x".to_string(),
+                x,
+                x != DirectionalInput::None && x != from_x,
+            ));
+            expressions.push(MatchExpression::Directional(
+                "y".to_string(),
+                y,
+                y != DirectionalInput::None && y != from_y,
+            ));
+
+            previous = Some(direction);
+        }
+
+        if !step.any_of.is_empty() {
+            expressions.push(MatchExpression::Direction(step.any_of.clone()));
+        }
+
+        if let Some((direction, frames)) = step.charge {
+            expressions.push(MatchExpression::Charge {
+                charge: charge_directions(direction),
+                frames,
+                leniency: CHARGE_LENIENCY,
+            });
+
+            previous = Some(direction);
+        }
+
+        list.push(expressions);
+    }
+
+    list.reverse();
+    list
+}
+
+fn next_direction(steps: &[Step], i: usize) -> Option<u8> {
+    steps[i + 1..].iter().find_map(|step| step.direction)
+}
+
+#[test]
+fn notation_compiles_and_round_trips() {
+    use DirectionalInput::*;
+
+    let x = |value, just_pressed| MatchExpression::Directional("x".to_string(), value, just_pressed);
+    let y = |value, just_pressed| MatchExpression::Directional("y".to_string(), value, just_pressed);
+    let a = MatchExpression::Button("a".to_string(), ButtonPress::Press);
+
+    assert_eq!(
+        parse("236A").unwrap(),
+        vec![
+            vec![a.clone(), x(Positive, false), y(None, false)],
+            vec![x(Positive, true), y(Negative, false)],
+            vec![x(None, false), y(Negative, false)],
+        ]
+    );
+    assert_eq!(parse("623+C").unwrap(), parse("623C").unwrap());
+    assert_eq!(parse("A+B").unwrap(), parse("AB").unwrap());
+    assert_eq!(
+        parse("~A J").unwrap(),
+        vec![
+            vec![MatchExpression::Button("lk".to_string(), ButtonPress::Press)],
+            vec![MatchExpression::Button("a".to_string(), ButtonPress::Release)],
+        ]
+    );
+    assert!(parse("236Z").is_err());
+
+    assert_eq!(
+        parse("(123)A").unwrap(),
+        vec![vec![a.clone(), MatchExpression::Direction(vec![1, 2, 3])]]
+    );
+
+    for notation in ["236A", "2[3]6A", "{4}6B", "{2:30}8C", "623C", "~A", "66 A", "AB", "[K]", "5A", "(123)A", "2(3)6A"] {
+        assert_eq!(to_notation(&parse(notation).unwrap()).as_deref(), Some(notation));
+    }
+
+    // A held direction is still just a direction, not a charge
+    assert_eq!(
+        parse("[4]").unwrap(),
+        vec![vec![x(Negative, false), y(None, false)]]
+    );
+    assert!(parse("{4:60}6A").is_ok());
+    assert!(parse("{4:61}6A").is_err());
+    assert!(parse("{4").is_err());
+
+    assert!(to_notation(&[vec![x(Positive, true)]]).is_none());
+}
@@ -21,7 +21,7 @@ use parry3d::shape::{Capsule, Cuboid};
 use ggrs::Config;
 
 use fighter::state::{State as FightState, CurrentState, ActiveState, PassiveState};
-use crate::fighter::state::{SerializedStateVec, SerializedState, StateMap, Owner, HBox};
+use crate::fighter::state::{SerializedStateVec, SerializedState, StateMap, Owner, HBox, HurtboxData, CancelRoute, CancelTarget, SerializedCancelRoute};
 use crate:: fighter::hit::components::HitboxData;
 use crate::fighter::data::Collider;
 use crate::battle::{PlayerEntities, PlayerHandleAccess};
@@ -71,6 +71,8 @@ fn populate_entities_with_states(
 ) {
     let mut state_map = StateMap::new();
     let mut transition_list: Vec<(Entity, Vec<u16>)> = Vec::new();
+    let mut cancel_list: Vec<(Entity, Vec<SerializedCancelRoute>)> = Vec::new();
+    let mut groups: HashMap<String, Vec<u16>> = HashMap::new();
 
     let mut global_hitbox_id: u32 = 0;
 
@@ -106,6 +108,10 @@ fn populate_entities_with_states(
             //let raw_name = state.debug_name.take().unwrap_or("State".to_string());
 
             transition_list.push((entity, transitions_serialized));
+            cancel_list.push((entity, std::mem::take(&mut state.cancels)));
+            for group in state.groups.iter() {
+                groups.entry(group.clone()).or_default().push(state.id);
+            }
 
             let mut state = FightState::from_serialized(state);
 
@@ -134,49 +140,20 @@ fn populate_entities_with_states(
             }
 
             // HURTBOXES
-            // if let Some(hurtboxes) = hurtbox_serialized {
-            //     let mut ordered_hurt: HashMap<u16, HashSet<Entity>> = HashMap::new();
-
-            //     for hurtbox in hurtboxes {
-            //         // let shape = shapes::Rectangle {
-            //         //     extents: hurtbox.dimensions.truncate(),
-            //         //     origin: RectangleOrigin::Center,
-            //         // };
-
-            //         //let cuboid = Cuboid::new((hurtbox.dimensions / 2.).into());
-            //         let capsule = Capsule::new_y(hurtbox.half_height, hurtbox.radius);
-
-            //         let start_frame = hurtbox.start_frame.unwrap_or_default();
-            //         let hurtbox_entity = world
-            //             .spawn()
-            //             .insert(hurtbox)
-            //             .insert(Rollback::new(rip.next_id()))
-            //             .insert(Collider { shape: capsule })
-            //             .insert(Name::new(format!("Hurtbox {}", &name)))
-            //             .insert(Owner(player))
-            //             // .insert_bundle(GeometryBuilder::build_as(
-            //             //     &shape,
-            //             //     DrawMode::Fill(FillMode::color(Color::rgba(1., 1., 0., 0.8))),
-            //             //     Transform::default(),
-            //             // ))
-            //             .insert_bundle(VisibilityBundle {
-            //                 visibility: Visibility { is_visible: false },
-            //                 computed: ComputedVisibility::default(),
-            //             })
-            //             .id();
-
-            //         if ordered_hurt.contains_key(&start_frame) {
-            //             let set = ordered_hurt.get_mut(&start_frame).unwrap();
-            //             set.insert(hurtbox_entity);
-            //         } else {
-            //             let mut set = HashSet::<Entity>::new();
-            //             set.insert(hurtbox_entity);
-            //             ordered_hurt.insert(start_frame, set);
-            //         }
-            //     }
-
-            //     state.add_hurtboxes(ordered_hurt);
-            // }
+            if let Some(hurtboxes) = hurtbox_serialized {
+                let mut ordered: HashMap<u16, Vec<HurtboxData>> = HashMap::new();
+
+                for (index, mut hurtbox) in hurtboxes.into_iter().enumerate() {
+                    hurtbox.set_id(index);
+                    // Shares the ids of the hitboxes, so both fit in the HitboxMap
+                    hurtbox.set_global_id(global_hitbox_id);
+                    global_hitbox_id += 1;
+
+                    let start_frame = hurtbox.start_frame.unwrap_or(1);
+                    ordered.entry(start_frame).or_default().push(hurtbox);
+                }
+                state.add_hurtboxes(ordered);
+            }
 
             // MODIFIERS
             if let Some(modifiers) = mods_serialized {
@@ -257,6 +234,27 @@ fn populate_entities_with_states(
             }
         }
 
+        for (s, cancels) in cancel_list {
+            let routes = cancels
+                .into_iter()
+                .map(|route| CancelRoute {
+                    on: route.on,
+                    window: route.window,
+                    targets: route
+                        .targets
+                        .iter()
+                        .flat_map(|target| match target {
+                            CancelTarget::Id(id) => vec![*id],
+                            CancelTarget::Group(group) => groups.get(group).cloned().unwrap_or_default(),
+                        })
+                        .map(|id| *state_map.get(&id).expect("Cancel target state doesn't exist"))
+                        .collect(),
+                })
+                .collect();
+
+            world.get_mut::<FightState>(s).unwrap().cancels = routes;
+        }
+
         world.entity_mut(player)
             .insert(CurrentState(*state_map.get(&0).expect("State with ID of 0 doesn't exist")))
             .insert(state_map);
@@ -291,6 +289,22 @@ pub fn insert_meshes(
                 }
             }
         }
+
+        if let Some(hurtboxes) = &state.hurtboxes {
+            for hurtbox in hurtboxes.values().flatten() {
+                let mesh = meshes.add(Mesh::from(shape::Capsule {
+                    radius: hurtbox.radius,
+                    depth: hurtbox.half_height * 2. - hurtbox.radius * 2.,
+                    ..default()
+                }));
+
+                let capsule = Capsule::new_y(hurtbox.half_height - hurtbox.radius, hurtbox.radius);
+                hitbox_resource.insert(
+                    hurtbox.global_id.expect("GlobalID doesn't exist"),
+                    (mesh, capsule.into()),
+                );
+            }
+        }
     }
 
     commands.insert_resource(HitboxMap(hitbox_resource));
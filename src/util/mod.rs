@@ -49,6 +49,7 @@ impl Buffer {
     }
 }
 
+#[derive(Clone)]
 pub struct BufferIter<'a> {
     ring: &'a [u32],
     tail: usize,